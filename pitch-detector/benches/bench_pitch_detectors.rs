@@ -1,5 +1,10 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use pitch_detector::pitch::{HannedFftDetector, PitchDetector, PowerCepstrum};
+use pitch_detector::core::constants::{MAX_FREQ, MIN_FREQ};
+use pitch_detector::core::fft_space::FftSpace;
+use pitch_detector::pitch::{
+    Autocorrelation2, Cepstrum2, HannedFftDetector, PitchDetector, PowerCepstrum, SwipeDetector,
+};
+use rustfft::FftPlanner;
 
 pub fn test_signal(filename: &str) -> anyhow::Result<Vec<f64>> {
     let file_path = format!(
@@ -15,6 +20,9 @@ fn criterion_benchmark(c: &mut Criterion) {
     let signal = test_signal("cello_open_a.wav").expect("Test file should exist");
     let mut hanned_detector = HannedFftDetector::default();
     let mut cepstrum_detector = PowerCepstrum::default();
+    let mut cepstrum2_detector = Cepstrum2::default();
+    let mut yin_detector = Autocorrelation2::new(0.1);
+    let mut swipe_detector = SwipeDetector::default();
     let sample_rate = 44100.;
     let mut group = c.benchmark_group("reduced_samples_group");
     group.significance_level(0.1).sample_size(60);
@@ -32,8 +40,52 @@ fn criterion_benchmark(c: &mut Criterion) {
                 .unwrap()
         })
     });
+    group.bench_function("cepstrum2", |b| {
+        b.iter(|| {
+            cepstrum2_detector
+                .detect_pitch_in_range(&signal, black_box(sample_rate), MIN_FREQ..MAX_FREQ)
+                .unwrap()
+        })
+    });
+    group.bench_function("autocorrelation2", |b| {
+        b.iter(|| {
+            yin_detector
+                .detect_pitch_in_range(&signal, black_box(sample_rate), MIN_FREQ..MAX_FREQ)
+                .unwrap()
+        })
+    });
+    group.bench_function("swipe", |b| {
+        b.iter(|| {
+            swipe_detector
+                .detect_pitch_in_range(&signal, black_box(sample_rate), MIN_FREQ..MAX_FREQ)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// Compares planning the FFT on every transform, as the detectors used to, with the plans cached by `FftSpace`.
+fn fft_plan_benchmark(c: &mut Criterion) {
+    let signal = test_signal("cello_open_a.wav").expect("Test file should exist");
+    let mut fft_space = FftSpace::new(signal.len());
+    let mut group = c.benchmark_group("fft_plan_group");
+    group.significance_level(0.1).sample_size(60);
+    group.bench_function("planned every call", |b| {
+        b.iter(|| {
+            fft_space.init_with_signal(signal.iter());
+            let fft = FftPlanner::new().plan_fft_forward(fft_space.padded_len());
+            let (space, scratch) = fft_space.workspace();
+            fft.process_with_scratch(space, scratch);
+        })
+    });
+    group.bench_function("cached plan", |b| {
+        b.iter(|| {
+            fft_space.init_with_signal(signal.iter());
+            fft_space.forward_fft();
+        })
+    });
     group.finish();
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, criterion_benchmark, fft_plan_benchmark);
 criterion_main!(benches);
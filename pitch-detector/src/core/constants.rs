@@ -10,3 +10,9 @@ pub const NOTES: [&str; 12] = [
 pub const MAX_CENTS_OFFSET: f64 = 10.0;
 
 pub const NUM_CENTS_BETWEEN_NOTES: f64 = 100.;
+
+/// Open strings of a guitar in standard tuning (E2 A2 D3 G3 B3 E4)
+pub const GUITAR_STANDARD_TUNING: [f64; 6] = [82.41, 110.0, 146.83, 196.0, 246.94, 329.63];
+
+/// Open strings of a cello (C2 G2 D3 A3)
+pub const CELLO_TUNING: [f64; 4] = [65.41, 98.0, 146.83, 220.0];
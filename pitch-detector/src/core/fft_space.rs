@@ -1,5 +1,9 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
 use num_traits::Zero;
-use rustfft::num_complex::Complex;
+use rustfft::{num_complex::Complex, Fft, FftDirection, FftPlanner};
+
+use super::{sample::Sample, window::Window};
 
 mod utils {
     use rustfft::num_complex::Complex;
@@ -32,30 +36,142 @@ mod utils {
     }
 }
 
+/// Forward and inverse FFT plans, keyed by length. Planning an FFT is expensive compared to running it, so every
+/// length is only planned the first time it is needed.
+#[derive(Clone, Default)]
+pub struct FftPlans {
+    forward: HashMap<usize, Arc<dyn Fft<f64>>>,
+    inverse: HashMap<usize, Arc<dyn Fft<f64>>>,
+}
+
+impl FftPlans {
+    pub fn forward(&mut self, len: usize) -> Arc<dyn Fft<f64>> {
+        self.plan(len, FftDirection::Forward)
+    }
+
+    pub fn inverse(&mut self, len: usize) -> Arc<dyn Fft<f64>> {
+        self.plan(len, FftDirection::Inverse)
+    }
+
+    fn plan(&mut self, len: usize, direction: FftDirection) -> Arc<dyn Fft<f64>> {
+        let plans = match direction {
+            FftDirection::Forward => &mut self.forward,
+            FftDirection::Inverse => &mut self.inverse,
+        };
+        plans
+            .entry(len)
+            .or_insert_with(|| FftPlanner::new().plan_fft(len, direction))
+            .clone()
+    }
+}
+
+impl fmt::Debug for FftPlans {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FftPlans")
+            .field("forward", &self.forward.keys().collect::<Vec<_>>())
+            .field("inverse", &self.inverse.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// How signals are zero padded before their FFT. Padding doesn't add any information to the spectrum, but it samples
+/// the spectrum more finely, which helps interpolating its peaks when signals are short.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Padding {
+    /// The padded size is at least the signal length times this factor
+    factor: usize,
+
+    /// Minimum padded size, regardless of the signal length
+    min_size: usize,
+
+    /// Whether the padded size is rounded up to a power of two, for which FFTs are fastest
+    power_of_two: bool,
+}
+
+impl Padding {
+    pub fn new(factor: usize) -> Self {
+        Self {
+            factor,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_min_size(self, min_size: usize) -> Self {
+        Self { min_size, ..self }
+    }
+
+    pub fn with_power_of_two(self, power_of_two: bool) -> Self {
+        Self {
+            power_of_two,
+            ..self
+        }
+    }
+
+    /// Size of the FFT for a signal of `signal_len` samples.
+    pub fn padded_size(&self, signal_len: usize) -> usize {
+        let size = (signal_len * self.factor.max(1)).max(self.min_size).max(1);
+        if self.power_of_two {
+            size.next_power_of_two()
+        } else {
+            size
+        }
+    }
+}
+
+impl Default for Padding {
+    /// Pads to the next power of two, and to at least 1024 samples.
+    fn default() -> Self {
+        Self {
+            factor: 1,
+            min_size: 1024,
+            power_of_two: true,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FftSpace {
     signal_len: usize,
+    padding: Padding,
     space: Vec<Complex<f64>>,
     scratch: Vec<Complex<f64>>,
+    plans: FftPlans,
 }
 
 impl FftSpace {
     pub fn new(size: usize) -> Self {
-        let mut padded_size = (2usize).pow(10);
-        padded_size = loop {
-            if padded_size < size {
-                padded_size *= 2;
-            } else {
-                break padded_size;
-            }
-        };
+        Self::new_padded(size, Padding::default())
+    }
+
+    pub fn new_padded(size: usize, padding: Padding) -> Self {
+        let padded_size = padding.padded_size(size);
         FftSpace {
             signal_len: size,
+            padding,
             space: vec![Complex::zero(); padded_size],
             scratch: vec![Complex::zero(); padded_size],
+            plans: FftPlans::default(),
         }
     }
 
+    /// Resizes the space to fit signals of `size` samples. The plans of previous sizes are kept, so going back to a
+    /// previous size doesn't need to plan again, and the space only allocates when it grows beyond its capacity.
+    pub fn resize(&mut self, size: usize) {
+        self.signal_len = size;
+        self.space
+            .resize(self.padding.padded_size(size), Complex::zero());
+    }
+
+    /// Multiplies the signal by `window`, divided by the coherent gain of the window so that the amplitude of sines in
+    /// the spectrum doesn't depend on the window.
+    pub fn apply_window(&mut self, window: Window) {
+        let correction = 1. / window.coherent_gain(self.signal_len);
+        self.space
+            .iter_mut()
+            .zip(window.iter(self.signal_len))
+            .for_each(|(s, w)| s.re *= w * correction);
+    }
+
     pub fn map<F: Fn(&Complex<f64>) -> Complex<f64>>(&mut self, map_fn: F) {
         self.space.iter_mut().for_each(|f| {
             *f = map_fn(f);
@@ -74,23 +190,43 @@ impl FftSpace {
         &self.space
     }
 
-    pub fn signal(&self) -> Box<dyn Iterator<Item = f64> + '_> {
-        Box::new(self.space[..self.signal_len].iter().map(|f| f.re))
+    pub fn signal(&self) -> impl Iterator<Item = f64> + '_ {
+        self.space[..self.signal_len].iter().map(|f| f.re)
     }
 
     pub fn workspace(&mut self) -> (&mut [Complex<f64>], &mut [Complex<f64>]) {
         (&mut self.space, &mut self.scratch)
     }
 
+    /// Replaces the space with its forward FFT.
+    pub fn forward_fft(&mut self) {
+        let fft = self.plans.forward(self.space.len());
+        self.process_fft(fft);
+    }
+
+    /// Replaces the space with its inverse FFT, which is not normalized.
+    pub fn inverse_fft(&mut self) {
+        let fft = self.plans.inverse(self.space.len());
+        self.process_fft(fft);
+    }
+
+    fn process_fft(&mut self, fft: Arc<dyn Fft<f64>>) {
+        let scratch_len = fft.get_inplace_scratch_len();
+        if self.scratch.len() < scratch_len {
+            self.scratch.resize(scratch_len, Complex::zero());
+        }
+        fft.process_with_scratch(&mut self.space, &mut self.scratch[..scratch_len]);
+    }
+
     pub fn init_with_signal<Signal, Frame>(&mut self, signal: Signal)
     where
         Signal: Iterator<Item = Frame>,
-        Frame: std::borrow::Borrow<f64>,
+        Frame: Sample,
     {
         let signal_len = signal.size_hint().1.expect("Signal length is not known");
         assert!(signal_len <= self.space.len());
         signal.zip(self.space.iter_mut()).for_each(|(sample, fft)| {
-            fft.re = *sample.borrow();
+            fft.re = sample.to_f64();
             fft.im = 0.0;
         });
         self.space[signal_len..]
@@ -98,10 +234,40 @@ impl FftSpace {
             .for_each(|o| *o = Complex::zero())
     }
 
-    pub fn freq_domain_iter(&self, square_rooted: bool) -> utils::FreqDomainIter {
+    pub fn freq_domain_iter(&self, square_rooted: bool) -> utils::FreqDomainIter<'_> {
         utils::FreqDomainIter {
             complex_iter: self.space.iter(),
             square_rooted,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_are_reused() {
+        let mut plans = FftPlans::default();
+        let forward = plans.forward(1024);
+        assert!(Arc::ptr_eq(&forward, &plans.forward(1024)));
+        assert!(!Arc::ptr_eq(&forward, &plans.forward(2048)));
+        assert_eq!(plans.inverse(1024).fft_direction(), FftDirection::Inverse);
+    }
+
+    #[test]
+    fn it_pads_signals() {
+        assert_eq!(Padding::default().padded_size(100), 1024);
+        assert_eq!(Padding::default().padded_size(4097), 8192);
+        assert_eq!(Padding::new(4).padded_size(1000), 4096);
+        assert_eq!(
+            Padding::new(3).with_power_of_two(false).padded_size(1000),
+            3000
+        );
+        assert_eq!(Padding::new(1).with_min_size(0).padded_size(100), 128);
+        assert_eq!(
+            FftSpace::new_padded(100, Padding::new(2)).padded_len(),
+            1024
+        );
+    }
+}
@@ -1,21 +1,47 @@
 use std::ops::Range;
 
+use super::{
+    peak_interpolator::{GaussianInterpolator, PeakInterpolator},
+    sample::Sample,
+};
+
 /// This trait provides the necessary methods to analyze the frequency make-up of a signal. Note that the bin values of
 /// the resulting frequency domain might not always correspond to the traditional output of FFT, which is why additional
 /// methods like `bin_to_freq` and `freq_to_bin` are required.
 pub trait ToFrequencyDomain {
-    /// Creates a frequency domain Vec with the given signal and optional frequency range. If frequency range is provided,
-    /// the second parameter to the tuple is the sample rate, which is needed to correlate the frequency range to
-    /// a bin range.
-    fn to_frequency_domain(
+    /// Computes the frequency domain of the given signal and optional frequency range, and returns the bin at which
+    /// it starts. If frequency range is provided, the second parameter to the tuple is the sample rate, which is needed
+    /// to correlate the frequency range to a bin range. The frequency domain is kept by the implementor, so that
+    /// computing it again for signals of the same length doesn't allocate.
+    fn compute_frequency_domain<S: Sample>(
+        &mut self,
+        signal: &[S],
+        freq_range: Option<(Range<f64>, f64)>,
+    ) -> usize;
+
+    /// The frequency domain computed by the last call to
+    /// [compute_frequency_domain](Self::compute_frequency_domain).
+    fn frequency_domain(&self) -> &[f64];
+
+    /// Computes the frequency domain of the given signal, and returns the bin at which it starts along with the
+    /// frequency domain itself.
+    fn to_frequency_domain<S: Sample>(
         &mut self,
-        signal: &[f64],
+        signal: &[S],
         freq_range: Option<(Range<f64>, f64)>,
-    ) -> (usize, Vec<f64>);
+    ) -> (usize, &[f64]) {
+        let start_bin = self.compute_frequency_domain(signal, freq_range);
+        (start_bin, self.frequency_domain())
+    }
 
     /// Translates frequency bin in frequency domain to frequency in hertz
     fn bin_to_freq(&self, bin: f64, sample_rate: f64) -> f64;
 
     /// Translates frequency in hertz to frequency bin in frequency domain
     fn freq_to_bin(&self, freq: f64, sample_rate: f64) -> f64;
+
+    /// Interpolator used to find the peaks of the frequency domain between its bins
+    fn peak_interpolator(&self) -> &dyn PeakInterpolator {
+        &GaussianInterpolator
+    }
 }
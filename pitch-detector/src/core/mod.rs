@@ -4,7 +4,10 @@ pub mod constants;
 pub mod error;
 pub mod fft_space;
 pub mod into_frequency_domain;
+pub mod peak_interpolator;
+pub mod sample;
 pub mod utils;
+pub mod window;
 
 #[cfg(test)]
 pub mod test_utils;
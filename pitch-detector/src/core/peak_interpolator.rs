@@ -0,0 +1,251 @@
+use std::fmt::Debug;
+
+use rustfft::num_complex::Complex;
+
+use super::{
+    error::PitchError,
+    utils::{interpolated_peak_at, parabolic_interpolation},
+    FftPoint,
+};
+
+/// Estimates where the true peak of a spectrum lies between its bins, since the frequency of a sine rarely falls
+/// exactly on a bin.
+pub trait PeakInterpolator: Debug + Send + Sync {
+    /// Interpolates the peak of `spectrum` at `idx`, and returns its fractional bin and its magnitude. `bins` are the
+    /// complex FFT bins that `spectrum` was computed from, starting at the same bin, for interpolators that need
+    /// their phase. Detectors whose spectrum doesn't come straight from an FFT pass `None`.
+    fn interpolate_peak(
+        &self,
+        spectrum: &[f64],
+        bins: Option<&[Complex<f64>]>,
+        idx: usize,
+    ) -> Result<FftPoint, PitchError>;
+}
+
+fn uninterpolated(spectrum: &[f64], idx: usize) -> FftPoint {
+    FftPoint {
+        x: idx as f64,
+        y: spectrum[idx],
+    }
+}
+
+/// Fits a parabola through the peak and its two neighbors.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuadraticInterpolator;
+
+impl PeakInterpolator for QuadraticInterpolator {
+    fn interpolate_peak(
+        &self,
+        spectrum: &[f64],
+        _bins: Option<&[Complex<f64>]>,
+        idx: usize,
+    ) -> Result<FftPoint, PitchError> {
+        Ok(parabolic_interpolation(spectrum, idx))
+    }
+}
+
+/// Fits a parabola through the logarithm of the peak and its two neighbors. The main lobe of a Gaussian window is
+/// exactly a parabola in the log domain, and those of the Hann and Blackman windows are close to one, which makes it
+/// much more accurate than [QuadraticInterpolator] for them. Falls back to [QuadraticInterpolator] when the values
+/// are not all positive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogParabolicInterpolator;
+
+impl PeakInterpolator for LogParabolicInterpolator {
+    fn interpolate_peak(
+        &self,
+        spectrum: &[f64],
+        bins: Option<&[Complex<f64>]>,
+        idx: usize,
+    ) -> Result<FftPoint, PitchError> {
+        if idx == 0 || idx + 1 >= spectrum.len() {
+            return Ok(uninterpolated(spectrum, idx));
+        }
+        let neighbors = &spectrum[idx - 1..=idx + 1];
+        if neighbors.iter().any(|value| *value <= 0.) {
+            return QuadraticInterpolator.interpolate_peak(spectrum, bins, idx);
+        }
+        let log_neighbors = [neighbors[0].ln(), neighbors[1].ln(), neighbors[2].ln()];
+        let FftPoint { x, y } = parabolic_interpolation(&log_neighbors, 1);
+        Ok(FftPoint {
+            x: idx as f64 + x - 1.,
+            y: y.exp(),
+        })
+    }
+}
+
+/// Jain's method, which interpolates from the ratio of the peak to its larger neighbor. Meant for spectra of
+/// rectangular windowed signals.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JainInterpolator;
+
+impl PeakInterpolator for JainInterpolator {
+    fn interpolate_peak(
+        &self,
+        spectrum: &[f64],
+        _bins: Option<&[Complex<f64>]>,
+        idx: usize,
+    ) -> Result<FftPoint, PitchError> {
+        if idx == 0 || idx + 1 >= spectrum.len() {
+            return Ok(uninterpolated(spectrum, idx));
+        }
+        let (left, center, right) = (spectrum[idx - 1], spectrum[idx], spectrum[idx + 1]);
+        let x = if left > right {
+            let ratio = center / left;
+            (idx - 1) as f64 + ratio / (1. + ratio)
+        } else {
+            let ratio = right / center;
+            idx as f64 + ratio / (1. + ratio)
+        };
+        if !x.is_finite() {
+            return Ok(uninterpolated(spectrum, idx));
+        }
+        Ok(FftPoint { x, y: center })
+    }
+}
+
+/// Quinn's second estimator, which interpolates from the ratios of the complex bins next to the peak to the peak
+/// itself. It is the most accurate of the interpolators for rectangular windowed signals, but it needs the complex
+/// bins, so it returns an error when they are not available.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuinnInterpolator;
+
+impl QuinnInterpolator {
+    fn tau(x: f64) -> f64 {
+        let root = (2f64 / 3.).sqrt();
+        0.25 * (3. * x * x + 6. * x + 1.).ln()
+            - 6f64.sqrt() / 24. * ((x + 1. - root) / (x + 1. + root)).ln()
+    }
+}
+
+impl PeakInterpolator for QuinnInterpolator {
+    fn interpolate_peak(
+        &self,
+        spectrum: &[f64],
+        bins: Option<&[Complex<f64>]>,
+        idx: usize,
+    ) -> Result<FftPoint, PitchError> {
+        let bins = bins.ok_or_else(|| {
+            PitchError::IncorrectParameters(
+                "Quinn's estimator needs the complex bins of the spectrum".to_string(),
+            )
+        })?;
+        if idx == 0 || idx + 1 >= spectrum.len().min(bins.len()) {
+            return Ok(uninterpolated(spectrum, idx));
+        }
+        let center_norm = bins[idx].norm_sqr();
+        if center_norm == 0. {
+            return Ok(uninterpolated(spectrum, idx));
+        }
+        let ratio = |neighbor: Complex<f64>| (neighbor * bins[idx].conj()).re / center_norm;
+        let (left_ratio, right_ratio) = (ratio(bins[idx - 1]), ratio(bins[idx + 1]));
+        let left_delta = left_ratio / (1. - left_ratio);
+        let right_delta = -right_ratio / (1. - right_ratio);
+        let delta = (left_delta + right_delta) / 2. + Self::tau(right_delta * right_delta)
+            - Self::tau(left_delta * left_delta);
+        if !delta.is_finite() {
+            return Ok(uninterpolated(spectrum, idx));
+        }
+        Ok(FftPoint {
+            x: idx as f64 + delta,
+            y: spectrum[idx],
+        })
+    }
+}
+
+/// Fits a gaussian to all the points of the peak, with [interpolated_peak_at].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GaussianInterpolator;
+
+impl PeakInterpolator for GaussianInterpolator {
+    fn interpolate_peak(
+        &self,
+        spectrum: &[f64],
+        _bins: Option<&[Complex<f64>]>,
+        idx: usize,
+    ) -> Result<FftPoint, PitchError> {
+        interpolated_peak_at(spectrum, idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{fft_space::FftSpace, utils::sine_wave_signal, window::Window};
+
+    const SIGNAL_LEN: usize = 4096;
+
+    /// Returns the magnitudes and the complex bins of the first half of the spectrum of `signal`.
+    fn spectrum(signal: &[f64], window: Window) -> (Vec<f64>, Vec<Complex<f64>>) {
+        let mut fft_space = FftSpace::new(signal.len());
+        fft_space.init_with_signal(signal.iter());
+        fft_space.apply_window(window);
+        fft_space.forward_fft();
+        let bins = fft_space.space()[..signal.len() / 2].to_vec();
+        (bins.iter().map(|bin| bin.norm()).collect(), bins)
+    }
+
+    /// Asserts that `interpolator` finds sines between bins 1000 and 1001 within `max_error` bins.
+    fn assert_off_bin_accuracy<I: PeakInterpolator>(
+        interpolator: I,
+        window: Window,
+        max_error: f64,
+    ) -> anyhow::Result<()> {
+        for offset in [0.1, 0.25, 0.4, 0.5, 0.6, 0.75, 0.9] {
+            let expected_bin = 1000. + offset;
+            let signal = sine_wave_signal(SIGNAL_LEN, expected_bin, SIGNAL_LEN as f64);
+            let (spectrum, bins) = spectrum(&signal, window);
+            let max_bin = spectrum
+                .iter()
+                .enumerate()
+                .reduce(|accum, item| if item.1 > accum.1 { item } else { accum })
+                .unwrap()
+                .0;
+            let peak = interpolator.interpolate_peak(&spectrum, Some(&bins), max_bin)?;
+            assert!(
+                (peak.x - expected_bin).abs() < max_error,
+                "{:?} with {:?}: expected bin {}, actual bin {}",
+                interpolator,
+                window,
+                expected_bin,
+                peak.x
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn quadratic_interpolates_off_bin_sines() -> anyhow::Result<()> {
+        assert_off_bin_accuracy(QuadraticInterpolator, Window::Hann, 0.06)
+    }
+
+    #[test]
+    fn log_parabolic_interpolates_off_bin_sines() -> anyhow::Result<()> {
+        assert_off_bin_accuracy(LogParabolicInterpolator, Window::Hann, 0.02)?;
+        assert_off_bin_accuracy(LogParabolicInterpolator, Window::Gaussian(0.3), 0.002)
+    }
+
+    #[test]
+    fn jain_interpolates_off_bin_sines() -> anyhow::Result<()> {
+        assert_off_bin_accuracy(JainInterpolator, Window::Rectangular, 0.001)
+    }
+
+    #[test]
+    fn quinn_interpolates_off_bin_sines() -> anyhow::Result<()> {
+        assert_off_bin_accuracy(QuinnInterpolator, Window::Rectangular, 0.001)
+    }
+
+    #[test]
+    fn gaussian_interpolates_off_bin_sines() -> anyhow::Result<()> {
+        assert_off_bin_accuracy(GaussianInterpolator, Window::Hann, 0.02)
+    }
+
+    #[test]
+    fn quinn_needs_complex_bins() {
+        let spectrum = [0., 1., 2., 1., 0.];
+        assert!(matches!(
+            QuinnInterpolator.interpolate_peak(&spectrum, None, 2),
+            Err(PitchError::IncorrectParameters(_))
+        ));
+    }
+}
@@ -0,0 +1,86 @@
+/// A single sample of a signal. Detectors accept signals of any sample type, and convert the samples to `f64` as they
+/// read them. Integer samples are normalized to the `[-1, 1]` range by dividing them by the magnitude of their minimum
+/// value, so that signal levels are the same whatever the type of the samples. Detectors always compute in `f64`.
+pub trait Sample: Copy + Send + Sync {
+    fn to_f64(self) -> f64;
+
+    /// Returns the signal as a slice of `f64`. Signals that are not `f64` already are converted into `buffer`, which
+    /// detectors keep between calls so that converting doesn't allocate once the buffer has grown to the length of
+    /// the signal.
+    fn to_f64_signal<'a>(signal: &'a [Self], buffer: &'a mut Vec<f64>) -> &'a [f64] {
+        buffer.clear();
+        buffer.extend(signal.iter().map(|s| s.to_f64()));
+        buffer
+    }
+}
+
+impl Sample for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn to_f64_signal<'a>(signal: &'a [Self], _buffer: &'a mut Vec<f64>) -> &'a [f64] {
+        signal
+    }
+}
+
+impl Sample for f32 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Sample for i16 {
+    fn to_f64(self) -> f64 {
+        self as f64 / 32768.
+    }
+}
+
+impl Sample for i32 {
+    fn to_f64(self) -> f64 {
+        self as f64 / 2147483648.
+    }
+}
+
+impl<S: Sample> Sample for &S {
+    fn to_f64(self) -> f64 {
+        (*self).to_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f64_signals_are_borrowed() {
+        let signal = [0.5, -0.5];
+        let mut buffer = vec![];
+        assert!(std::ptr::eq(
+            Sample::to_f64_signal(&signal[..], &mut buffer),
+            &signal[..]
+        ));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn other_signals_are_converted_into_the_buffer() {
+        let mut buffer = vec![];
+        let signal = [16384i16, -32768, 0];
+        assert_eq!(
+            Sample::to_f64_signal(&signal[..], &mut buffer),
+            [0.5, -1., 0.]
+        );
+        let capacity = buffer.capacity();
+        let signal = [3f32, -3.];
+        assert_eq!(Sample::to_f64_signal(&signal[..], &mut buffer), [3., -3.]);
+        assert_eq!(buffer.capacity(), capacity);
+    }
+
+    #[test]
+    fn integer_samples_are_normalized() {
+        assert_eq!(i16::MIN.to_f64(), -1.);
+        assert_eq!(i32::MIN.to_f64(), -1.);
+        assert_eq!(0x4000i16.to_f64(), 0x4000_0000i32.to_f64());
+    }
+}
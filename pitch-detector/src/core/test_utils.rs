@@ -3,6 +3,8 @@ use float_cmp::ApproxEq;
 use crate::{
     core::{
         constants::{MAX_FREQ, MIN_FREQ},
+        error::PitchError,
+        sample::Sample,
         utils::sine_wave_signal,
     },
     pitch::PitchDetector,
@@ -38,7 +40,7 @@ pub mod hinted {
         assert_eq!(
             detector
                 .detect_note_with_hint_and_range(
-                    expected_note.clone(),
+                    expected_note,
                     &signal,
                     SAMPLE_RATE,
                     Some(MIN_FREQ..MAX_FREQ)
@@ -111,3 +113,225 @@ pub fn test_sine_wave<D: PitchDetector>(detector: &mut D, freq: f64) -> anyhow::
     );
     Ok(())
 }
+
+/// Deterministic white noise in the range [-0.5, 0.5), generated with a xorshift generator.
+pub fn white_noise_signal(num_samples: usize) -> Vec<f64> {
+    let mut state = 88172645463325252u64;
+    (0..num_samples)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+        })
+        .collect()
+}
+
+/// Asserts that the detector is more confident about a recording than about white noise, or that it detects no pitch
+/// in the noise at all.
+pub fn test_confidence<D: PitchDetector>(detector: &mut D) -> anyhow::Result<()> {
+    const SAMPLE_RATE: f64 = 44100.0;
+    let recording = test_signal("cello_open_a.wav")?;
+    let noise = white_noise_signal(recording.len());
+
+    let pitched =
+        detector.detect_with_confidence_in_range(&recording, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+    assert!((0.0..=1.0).contains(&pitched.confidence));
+    assert!(pitched.signal_level > 0.);
+    match detector.detect_with_confidence_in_range(&noise, SAMPLE_RATE, MIN_FREQ..MAX_FREQ) {
+        Ok(unpitched) => assert!(
+            pitched.confidence > unpitched.confidence,
+            "Expected confidence {} to exceed confidence of noise {}",
+            pitched.confidence,
+            unpitched.confidence
+        ),
+        Err(PitchError::NoPitchDetected(_)) => {}
+        Err(err) => panic!("Expected noise to have no pitch, got {:?}", err),
+    }
+    Ok(())
+}
+
+/// Asserts that the detector detects the same pitch and signal level in a recording regardless of the type of its
+/// samples.
+pub fn test_sample_types<D>(detector: &mut D) -> anyhow::Result<()>
+where
+    D: PitchDetector<f64> + PitchDetector<f32> + PitchDetector<i16> + PitchDetector<i32>,
+{
+    const SAMPLE_RATE: f64 = 44100.0;
+    let recording = test_signal("cello_open_a.wav")?;
+    let i16_signal: Vec<i16> = recording.iter().map(|s| *s as i16).collect();
+    let i32_signal: Vec<i32> = recording.iter().map(|s| (*s as i32) << 16).collect();
+    let signal: Vec<f64> = recording.iter().map(|s| s / 32768.).collect();
+    let f32_signal: Vec<f32> = signal.iter().map(|s| *s as f32).collect();
+
+    let expected =
+        detector.detect_with_confidence_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+    for detection in [
+        detector.detect_with_confidence_in_range(&i16_signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?,
+        detector.detect_with_confidence_in_range(&i32_signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?,
+        detector.detect_with_confidence_in_range(&f32_signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?,
+    ] {
+        assert!(
+            detection.freq.approx_eq(expected.freq, (1e-6, 2)),
+            "Expected freq: {}, Actual freq: {}",
+            expected.freq,
+            detection.freq
+        );
+        assert!(
+            detection
+                .signal_level
+                .approx_eq(expected.signal_level, (1e-6, 2)),
+            "Expected signal level: {}, Actual signal level: {}",
+            expected.signal_level,
+            detection.signal_level
+        );
+    }
+    Ok(())
+}
+
+/// Global allocator that counts the allocations of each thread, so that tests running in parallel don't count each
+/// other's allocations.
+pub mod allocations {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.with(|count| count.set(count.get() + 1));
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Number of allocations made by the current thread while running `f`.
+    pub fn count_allocations<F: FnOnce()>(f: F) -> usize {
+        let before = ALLOCATIONS.with(|count| count.get());
+        f();
+        ALLOCATIONS.with(|count| count.get()) - before
+    }
+}
+
+/// Asserts that once the detector has been warmed up with the signal, detecting its pitch again doesn't allocate.
+fn assert_no_allocations<S: Sample, D: PitchDetector<S> + ?Sized>(
+    name: &str,
+    detector: &mut D,
+    signal: &[S],
+) -> anyhow::Result<()> {
+    const SAMPLE_RATE: f64 = 44100.0;
+    detector.detect_pitch_in_range(signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+
+    let mut result = Ok(0.);
+    let allocations = allocations::count_allocations(|| {
+        result = detector.detect_pitch_in_range(signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ);
+    });
+    result?;
+    assert_eq!(
+        allocations,
+        0,
+        "{} allocated while detecting {} samples",
+        name,
+        std::any::type_name::<S>()
+    );
+    Ok(())
+}
+
+/// Asserts that a detector that is reused with signals of different lengths detects the same pitches as new
+/// detectors.
+pub fn test_changing_signal_lengths<D: PitchDetector + Default>(
+    detector: &mut D,
+) -> anyhow::Result<()> {
+    const SAMPLE_RATE: f64 = 44100.0;
+    let recording = test_signal("cello_open_a.wav")?;
+    for len in [2048, 16384, 4096] {
+        let signal = &recording[..len];
+        let freq = detector.detect_pitch_in_range(signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+        let expected_freq =
+            D::default().detect_pitch_in_range(signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+        assert_eq!(freq, expected_freq, "Signal length: {}", len);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::constants::CELLO_TUNING,
+        pitch::{
+            Autocorrelation2, Cepstrum2, ConstantQDetector, EnsembleDetector, GoertzelDetector,
+            HannedFftDetector, HpsDetector, McLeodDetector, PYinDetector, PowerCepstrum,
+            ShsDetector, SwipeDetector, VoicingGate, ZeroCrossingDetector,
+        },
+    };
+
+    trait F64AndF32Detector: PitchDetector<f64> + PitchDetector<f32> {}
+    impl<D: PitchDetector<f64> + PitchDetector<f32>> F64AndF32Detector for D {}
+
+    /// Every detector, once warmed up with a signal, detects the pitch of signals of the same length and sample type
+    /// without allocating, including signals that are converted to `f64`.
+    #[test]
+    fn detectors_are_allocation_free_after_warm_up() -> anyhow::Result<()> {
+        let ensemble = EnsembleDetector::new()
+            .with_member(Box::new(HannedFftDetector::default()), 1.)
+            .with_member(Box::new(PowerCepstrum::new_with_defaults()), 1.)
+            .with_member(Box::new(Autocorrelation2::new(0.1)), 1.)
+            .with_member(Box::new(McLeodDetector::default()), 1.)
+            .with_member(Box::new(HpsDetector::default()), 1.);
+        let detectors: Vec<(&str, Box<dyn F64AndF32Detector>)> = vec![
+            ("Autocorrelation2", Box::new(Autocorrelation2::new(0.1))),
+            ("Cepstrum2", Box::new(Cepstrum2::default())),
+            ("ConstantQDetector", Box::new(ConstantQDetector::default())),
+            ("EnsembleDetector", Box::new(ensemble)),
+            (
+                "GoertzelDetector",
+                Box::new(GoertzelDetector::new(CELLO_TUNING.to_vec())),
+            ),
+            ("HannedFftDetector", Box::new(HannedFftDetector::default())),
+            (
+                "HannedFftDetector with phase vocoder",
+                Box::new(HannedFftDetector::default().with_phase_vocoder(true)),
+            ),
+            ("HpsDetector", Box::new(HpsDetector::default())),
+            ("McLeodDetector", Box::new(McLeodDetector::default())),
+            ("PYinDetector", Box::new(PYinDetector::default())),
+            (
+                "PowerCepstrum",
+                Box::new(PowerCepstrum::new_with_defaults()),
+            ),
+            ("ShsDetector", Box::new(ShsDetector::default())),
+            ("SwipeDetector", Box::new(SwipeDetector::default())),
+            (
+                "VoicingGate",
+                Box::new(VoicingGate::new(HannedFftDetector::default())),
+            ),
+            ("ZeroCrossingDetector", Box::new(ZeroCrossingDetector)),
+        ];
+
+        let recording = test_signal("cello_open_a.wav")?;
+        let f32_recording: Vec<f32> = recording.iter().map(|s| *s as f32).collect();
+        for (name, mut detector) in detectors {
+            assert_no_allocations(name, detector.as_mut(), &recording)?;
+            assert_no_allocations(name, detector.as_mut(), &f32_recording)?;
+        }
+        Ok(())
+    }
+}
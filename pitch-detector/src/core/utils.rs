@@ -1,6 +1,4 @@
-use fitting::gaussian::fit;
-
-use super::{error::PitchError, FftPoint};
+use super::{error::PitchError, sample::Sample, FftPoint};
 
 pub fn sine_wave_signal(num_samples: usize, freq: f64, sample_rate: f64) -> Vec<f64> {
     (0..num_samples)
@@ -28,6 +26,136 @@ pub fn audio_buffer_to_signal(byte_buffer: &[u8]) -> Box<dyn Iterator<Item = f64
     Box::new(audio_buffer_to_samples(byte_buffer).map(|x| x as f64))
 }
 
+/// Number of times per second that the signal crosses its mean value, in either direction.
+pub fn zero_crossing_rate<S: Sample>(signal: &[S], sample_rate: f64) -> f64 {
+    if signal.len() < 2 {
+        return 0.;
+    }
+    let mean = signal.iter().map(|s| s.to_f64()).sum::<f64>() / signal.len() as f64;
+    let crossings = signal
+        .windows(2)
+        .filter(|pair| (pair[0].to_f64() >= mean) != (pair[1].to_f64() >= mean))
+        .count();
+    crossings as f64 * sample_rate / signal.len() as f64
+}
+
+/// Magnitude of the signal at an arbitrary frequency, computed with the Goertzel algorithm.
+pub fn goertzel_magnitude(signal: impl Iterator<Item = f64>, freq: f64, sample_rate: f64) -> f64 {
+    let omega = 2. * std::f64::consts::PI * freq / sample_rate;
+    let coeff = 2. * omega.cos();
+    let (s1, s2) = signal.fold((0., 0.), |(s1, s2), sample| (sample + coeff * s1 - s2, s1));
+    (s1 * s1 + s2 * s2 - coeff * s1 * s2).max(0.).sqrt()
+}
+
+/// Root mean square of the signal around its mean value.
+pub fn rms<S: Sample>(signal: &[S]) -> f64 {
+    if signal.is_empty() {
+        return 0.;
+    }
+    let mean = signal.iter().map(|s| s.to_f64()).sum::<f64>() / signal.len() as f64;
+    (signal
+        .iter()
+        .map(|s| (s.to_f64() - mean) * (s.to_f64() - mean))
+        .sum::<f64>()
+        / signal.len() as f64)
+        .sqrt()
+}
+
+/// One minus the ratio of the mean of `values` to `peak`. Close to 1 when `peak` stands out from the rest of the
+/// values, and close to 0 when the values are flat.
+pub fn peak_to_mean_confidence(values: &[f64], peak: f64) -> f64 {
+    if values.is_empty() || peak <= 0. {
+        return 0.;
+    }
+    1. - values.iter().sum::<f64>() / values.len() as f64 / peak
+}
+
+/// Fits a parabola through the point at `idx` and its two neighbors, and returns the vertex of the parabola. Works
+/// for both maxima and minima. If `idx` is at either end of `values`, the point itself is returned.
+pub fn parabolic_interpolation(values: &[f64], idx: usize) -> FftPoint {
+    if idx == 0 || idx + 1 >= values.len() {
+        return FftPoint {
+            x: idx as f64,
+            y: values[idx],
+        };
+    }
+    let (left, center, right) = (values[idx - 1], values[idx], values[idx + 1]);
+    let denominator = left - 2.0 * center + right;
+    if denominator == 0.0 {
+        return FftPoint {
+            x: idx as f64,
+            y: center,
+        };
+    }
+    let delta = 0.5 * (left - right) / denominator;
+    FftPoint {
+        x: idx as f64 + delta,
+        y: center - 0.25 * (left - right) * delta,
+    }
+}
+
+/// Solves the 3x3 linear system `a * x = b` with Gaussian elimination and partial pivoting. Returns `None` if the
+/// system has no unique solution.
+fn solve_3x3(mut a: [[f64; 3]; 3], mut b: [f64; 3]) -> Option<[f64; 3]> {
+    for i in 0..3 {
+        let pivot = (i..3).reduce(|best, j| {
+            if a[j][i].abs() > a[best][i].abs() {
+                j
+            } else {
+                best
+            }
+        })?;
+        if a[pivot][i] == 0. {
+            return None;
+        }
+        a.swap(i, pivot);
+        b.swap(i, pivot);
+        for j in i + 1..3 {
+            let coefficient = a[j][i] / a[i][i];
+            let pivot_row = a[i];
+            for (value, pivot_value) in a[j].iter_mut().zip(pivot_row).skip(i) {
+                *value -= pivot_value * coefficient;
+            }
+            b[j] -= b[i] * coefficient;
+        }
+    }
+    let mut x = [0.; 3];
+    for i in (0..3).rev() {
+        x[i] = (b[i] - (i + 1..3).map(|k| a[i][k] * x[k]).sum::<f64>()) / a[i][i];
+    }
+    x.iter().all(|v| v.is_finite()).then_some(x)
+}
+
+/// Fits a gaussian to the points of `values` in `range` with Guo's algorithm, which is a weighted least squares fit of
+/// a parabola to the logarithm of the values. Returns the peak of the gaussian. The x values are centered on `center`
+/// to keep the system well conditioned.
+fn gaussian_fit(
+    values: &[f64],
+    range: std::ops::RangeInclusive<usize>,
+    center: usize,
+) -> Result<FftPoint, PitchError> {
+    let mut a = [[0.; 3]; 3];
+    let mut b = [0.; 3];
+    for i in range {
+        let (x, y) = (i as f64 - center as f64, values[i]);
+        let y_pow2 = y * y;
+        let x_pows = [1., x, x * x, x * x * x, x * x * x * x];
+        for (row, b) in b.iter_mut().enumerate() {
+            for col in 0..3 {
+                a[row][col] += x_pows[row + col] * y_pow2;
+            }
+            *b += x_pows[row] * y_pow2 * y.ln();
+        }
+    }
+    let [a, b, c] = solve_3x3(a, b).ok_or_else(|| {
+        PitchError::UnexpectedError("Could not fit a gaussian to the peak".to_string())
+    })?;
+    Ok(FftPoint {
+        x: center as f64 - b / (2. * c),
+        y: (a - b * b / (4. * c)).exp(),
+    })
+}
+
 /// Fits the curve to which fft_point_x belongs to and returns the peak point
 pub fn interpolated_peak_at(spectrum: &[f64], fft_point_x: usize) -> Result<FftPoint, PitchError> {
     let mut idx = fft_point_x;
@@ -50,41 +178,40 @@ pub fn interpolated_peak_at(spectrum: &[f64], fft_point_x: usize) -> Result<FftP
         }
         idx += 1;
     };
-    let y_vals: Vec<f64> = spectrum[peak_begin_idx..=peak_end_idx_incl].to_vec();
-    let x_vals: Vec<f64> = (peak_begin_idx..=peak_end_idx_incl)
-        .map(|i| i as f64)
-        .collect();
-
-    assert_eq!(
-        y_vals.len(),
-        x_vals.len(),
-        "Expected y_vals and x_vals to equal in length"
-    );
-    match x_vals.len() {
-        0 => Err(PitchError::IncorrectParameters(
-            "Expected at least one x value".to_string(),
-        )),
+    match peak_end_idx_incl - peak_begin_idx + 1 {
         1 => Ok(FftPoint {
-            x: x_vals[0],
-            y: y_vals[0],
+            x: peak_begin_idx as f64,
+            y: spectrum[peak_begin_idx],
         }),
         2 => {
-            if y_vals[0] > y_vals[1] {
-                Ok(FftPoint {
-                    x: x_vals[0],
-                    y: y_vals[0],
-                })
+            let idx = if spectrum[peak_begin_idx] > spectrum[peak_end_idx_incl] {
+                peak_begin_idx
             } else {
-                Ok(FftPoint {
-                    x: x_vals[1],
-                    y: y_vals[1],
-                })
-            }
-        }
-        _ => {
-            let (mu, _, a) = fit(x_vals.into(), y_vals.into())
-                .map_err(|e| PitchError::UnexpectedError(e.to_string()))?;
-            Ok(FftPoint { x: mu, y: a })
+                peak_end_idx_incl
+            };
+            Ok(FftPoint {
+                x: idx as f64,
+                y: spectrum[idx],
+            })
         }
+        _ => gaussian_fit(spectrum, peak_begin_idx..=peak_end_idx_incl, fft_point_x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::ApproxEq;
+
+    #[test]
+    fn it_interpolates_gaussian_peaks() -> anyhow::Result<()> {
+        let (mu, sigma, a) = (10.3, 1.5, 2.);
+        let spectrum: Vec<f64> = (0..20)
+            .map(|x| a * (-(x as f64 - mu).powi(2) / (2. * sigma * sigma)).exp())
+            .collect();
+        let peak = interpolated_peak_at(&spectrum, 10)?;
+        assert!(peak.x.approx_eq(mu, (1e-9, 2)), "Actual x: {}", peak.x);
+        assert!(peak.y.approx_eq(a, (1e-9, 2)), "Actual y: {}", peak.y);
+        Ok(())
     }
 }
@@ -0,0 +1,145 @@
+use std::f64::consts::PI;
+
+/// Window functions that taper a signal before its FFT, to reduce the spectral leakage caused by analyzing a finite
+/// frame of the signal. Windows with lower side lobes, such as Blackman-Harris, leak less into distant bins, at the
+/// cost of wider peaks. Flat-top measures the amplitude of peaks most accurately, and rectangular leaves the signal
+/// untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+
+    /// Kaiser window with the given beta. Higher values of beta have lower side lobes and wider peaks. A beta of 0 is
+    /// a rectangular window, and a beta of about 6 is similar to Hann.
+    Kaiser(f64),
+
+    FlatTop,
+
+    /// Gaussian window with the given standard deviation, relative to half the length of the window. Typical values
+    /// are 0.5 or lower.
+    Gaussian(f64),
+}
+
+/// Zeroth order modified Bessel function of the first kind, computed with its power series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.;
+    let mut term = 1.;
+    let mut k = 1.;
+    while term > sum * 1e-12 {
+        term *= (x / (2. * k)).powi(2);
+        sum += term;
+        k += 1.;
+    }
+    sum
+}
+
+/// Generalized cosine window with the coefficients `a`, evaluated at `x` in [0, 1].
+fn cosine_sum(a: &[f64], x: f64) -> f64 {
+    a.iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1. } else { -1. };
+            sign * a * (2. * PI * k as f64 * x).cos()
+        })
+        .sum()
+}
+
+impl Window {
+    /// Value of the window at `index`, for a window of `size` samples. Windows are symmetric, so the first and last
+    /// samples have the same value.
+    pub fn value(&self, index: usize, size: usize) -> f64 {
+        if size <= 1 {
+            return 1.;
+        }
+        let x = index as f64 / (size - 1) as f64;
+        match *self {
+            Window::Rectangular => 1.,
+            Window::Hann => cosine_sum(&[0.5, 0.5], x),
+            Window::Hamming => cosine_sum(&[0.54, 0.46], x),
+            Window::Blackman => cosine_sum(&[0.42, 0.5, 0.08], x),
+            Window::BlackmanHarris => cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168], x),
+            Window::Kaiser(beta) => {
+                let r = 2. * x - 1.;
+                bessel_i0(beta * (1. - r * r).max(0.).sqrt()) / bessel_i0(beta)
+            }
+            Window::FlatTop => cosine_sum(
+                &[
+                    0.21557895,
+                    0.41663158,
+                    0.277263158,
+                    0.083578947,
+                    0.006947368,
+                ],
+                x,
+            ),
+            Window::Gaussian(sigma) => (-0.5 * ((2. * x - 1.) / sigma).powi(2)).exp(),
+        }
+    }
+
+    /// Values of a window of `size` samples.
+    pub fn iter(&self, size: usize) -> impl Iterator<Item = f64> + '_ {
+        (0..size).map(move |index| self.value(index, size))
+    }
+
+    /// Mean value of a window of `size` samples, which is how much the window scales the amplitude of a sine in the
+    /// spectrum.
+    pub fn coherent_gain(&self, size: usize) -> f64 {
+        if size == 0 {
+            return 1.;
+        }
+        self.iter(size).sum::<f64>() / size as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::ApproxEq;
+
+    const WINDOWS: [Window; 8] = [
+        Window::Rectangular,
+        Window::Hann,
+        Window::Hamming,
+        Window::Blackman,
+        Window::BlackmanHarris,
+        Window::Kaiser(8.),
+        Window::FlatTop,
+        Window::Gaussian(0.4),
+    ];
+
+    #[test]
+    fn hann_matches_apodize() {
+        for (actual, expected) in Window::Hann.iter(1024).zip(apodize::hanning_iter(1024)) {
+            assert!(actual.approx_eq(expected, (1e-12, 2)));
+        }
+    }
+
+    #[test]
+    fn windows_are_symmetric_and_peak_in_the_center() {
+        for window in WINDOWS {
+            let values: Vec<f64> = window.iter(1025).collect();
+            for (a, b) in values.iter().zip(values.iter().rev()) {
+                assert!(a.approx_eq(*b, (1e-9, 2)), "{:?} is not symmetric", window);
+            }
+            assert!(values[512].approx_eq(1., (1e-6, 2)), "{:?}", window);
+        }
+    }
+
+    #[test]
+    fn it_computes_coherent_gains() {
+        assert_eq!(Window::Rectangular.coherent_gain(1024), 1.);
+        assert!(Window::Hann.coherent_gain(1024).approx_eq(0.5, (1e-3, 2)));
+        assert!(Window::Hamming
+            .coherent_gain(1024)
+            .approx_eq(0.54, (1e-3, 2)));
+        assert!(Window::Blackman
+            .coherent_gain(1024)
+            .approx_eq(0.42, (1e-3, 2)));
+        assert!(Window::Kaiser(0.)
+            .coherent_gain(1024)
+            .approx_eq(1., (1e-9, 2)));
+    }
+}
@@ -89,4 +89,3 @@
 pub mod core;
 pub mod note;
 pub mod pitch;
-
@@ -6,30 +6,49 @@ use std::ops::Range;
 
 use crate::{
     core::{
-        error::PitchError, into_frequency_domain::ToFrequencyDomain, utils::interpolated_peak_at,
-        NoteName,
+        error::PitchError, into_frequency_domain::ToFrequencyDomain, sample::Sample,
+        utils::peak_to_mean_confidence, NoteName,
     },
     note::peak_detector::{PeakDetector, PeakFinderDetector},
+    pitch::PitchDetection,
 };
 
-use super::note_detection_result::NoteDetection;
+use super::{note_detection_result::NoteDetection, Tuning};
 
 pub trait HintedNoteDetector {
-    fn detect_note_with_hint(
+    fn detect_note_with_hint<S: Sample>(
         &mut self,
         note_hint: NoteName,
-        signal: &[f64],
+        signal: &[S],
         sample_rate: f64,
     ) -> Result<NoteDetection, PitchError> {
         self.detect_note_with_hint_and_range(note_hint, signal, sample_rate, None)
     }
 
-    fn detect_note_with_hint_and_range(
+    fn detect_note_with_hint_and_range<S: Sample>(
         &mut self,
         note_hint: NoteName,
-        signal: &[f64],
+        signal: &[S],
         sample_rate: f64,
         freq_range_hint: Option<Range<f64>>,
+    ) -> Result<NoteDetection, PitchError> {
+        self.detect_note_with_hint_and_tuning(
+            note_hint,
+            signal,
+            sample_rate,
+            freq_range_hint,
+            &Tuning::default(),
+        )
+    }
+
+    /// The hint and the resulting note are relative to `tuning` instead of A4 = 440 Hz.
+    fn detect_note_with_hint_and_tuning<S: Sample>(
+        &mut self,
+        note_hint: NoteName,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range_hint: Option<Range<f64>>,
+        tuning: &Tuning,
     ) -> Result<NoteDetection, PitchError>;
 }
 
@@ -37,36 +56,45 @@ impl<T> HintedNoteDetector for T
 where
     T: ToFrequencyDomain,
 {
-    fn detect_note_with_hint_and_range(
+    fn detect_note_with_hint_and_tuning<S: Sample>(
         &mut self,
         note_hint: NoteName,
-        signal: &[f64],
+        signal: &[S],
         sample_rate: f64,
         freq_range_hint: Option<Range<f64>>,
+        tuning: &Tuning,
     ) -> Result<NoteDetection, PitchError> {
-        let (start_bin, spectrum) =
-            self.to_frequency_domain(signal, freq_range_hint.map(|r| (r, sample_rate)));
+        let start_bin =
+            self.compute_frequency_domain(signal, freq_range_hint.map(|r| (r, sample_rate)));
+        let spectrum = self.frequency_domain();
         const THRESHOLD: f64 = 6.;
         let peak_detector = PeakFinderDetector::new(THRESHOLD);
-        let mut candidates = peak_detector.detect_peaks(&spectrum);
+        let mut candidates = peak_detector.detect_peaks(spectrum);
         candidates.sort_by(|a, b| b.partial_cmp(a).unwrap());
         let bin = candidates
             .iter()
             .find(|bin| {
                 let freq = self.bin_to_freq((bin.bin + start_bin) as f64, sample_rate);
-                let result = NoteDetection::try_from(freq);
+                let result = tuning.freq_to_note(freq);
                 if let Ok(result) = result {
                     result.note_name == note_hint
                 } else {
                     false
                 }
             })
-            .ok_or(PitchError::NoPitchDetected(
-                "Did not find pitch that matches hint".to_string(),
-            ))?;
-        let fft_point = interpolated_peak_at(&spectrum, bin.bin)?;
+            .ok_or_else(|| {
+                PitchError::NoPitchDetected("Did not find pitch that matches hint".to_string())
+            })?;
+        let fft_point = self
+            .peak_interpolator()
+            .interpolate_peak(spectrum, None, bin.bin)?;
         let freq = self.bin_to_freq(fft_point.x + start_bin as f64, sample_rate);
-        NoteDetection::try_from(freq)
+        // The hinted peak is not necessarily the highest one, in which case the confidence is lower
+        tuning.to_note(PitchDetection::new(
+            freq,
+            peak_to_mean_confidence(spectrum, spectrum[bin.bin]),
+            signal,
+        ))
     }
 }
 
@@ -76,6 +104,8 @@ mod tests {
         core::{
             constants::{MAX_FREQ, MIN_FREQ},
             test_utils::{hinted::assert_hinted_detector_sine_waves, test_signal},
+            utils::mixed_wave_signal,
+            window::Window,
         },
         pitch::HannedFftDetector,
     };
@@ -141,4 +171,44 @@ mod tests {
         assert_hinted_detector_sine_waves(&mut detector, NoteName::A, vec![440., 523.25])?;
         Ok(())
     }
+
+    #[test]
+    fn test_with_tuning() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let tuning = Tuning::from_a4_freq(415.);
+        let signal = mixed_wave_signal(
+            16384,
+            vec![415., tuning.note_freq(NoteName::C, 5).unwrap()],
+            SAMPLE_RATE,
+        );
+        let mut detector = HannedFftDetector::default();
+        let note = detector.detect_note_with_hint_and_tuning(
+            NoteName::A,
+            &signal,
+            SAMPLE_RATE,
+            Some(MIN_FREQ..MAX_FREQ),
+            &tuning,
+        )?;
+        assert_eq!(note.note_name, NoteName::A);
+        assert!(note.in_tune, "Cents offset: {}", note.cents_offset);
+
+        // At A4 = 440 Hz, the same signal has no A
+        assert!(detector
+            .detect_note_with_hint_and_range(
+                NoteName::A,
+                &signal,
+                SAMPLE_RATE,
+                Some(MIN_FREQ..MAX_FREQ)
+            )
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_window() -> anyhow::Result<()> {
+        let mut detector = HannedFftDetector::default().with_window(Window::BlackmanHarris);
+        assert_hinted_detector_sine_waves(&mut detector, NoteName::A, vec![440., 523.25])?;
+        assert_hinted_detector(&mut detector, "cello_open_d.wav", 44100., NoteName::D)?;
+        Ok(())
+    }
 }
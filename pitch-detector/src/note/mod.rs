@@ -1,13 +1,22 @@
 mod note_detection_result;
 pub mod peak_detector;
+mod scala;
+mod temperament;
+pub(crate) mod tuning;
 
 pub mod hinted;
 
 use std::ops::Range;
 
-use crate::{core::error::PitchError, pitch::PitchDetector};
+use crate::{
+    core::{error::PitchError, sample::Sample},
+    pitch::PitchDetector,
+};
 
 pub use self::note_detection_result::NoteDetection;
+pub use self::scala::{KeyboardMapping, Scale};
+pub use self::temperament::Temperament;
+pub use self::tuning::Tuning;
 
 /// Returns the predominant note of the given signal. It will detect within a conventional
 /// range of frequencies (20Hz to nyquist). If you want to detect a note in a specific range,
@@ -37,14 +46,56 @@ pub use self::note_detection_result::NoteDetection;
 /// # Ok(())
 /// # }
 /// ```
-pub fn detect_note<D: PitchDetector>(
-    signal: &[f64],
+pub fn detect_note<S: Sample, D: PitchDetector<S>>(
+    signal: &[S],
     freq_detector: &mut D,
     sample_rate: f64,
+) -> Result<NoteDetection, PitchError> {
+    detect_note_with_tuning(signal, freq_detector, sample_rate, &Tuning::default())
+}
+
+/// Same as [detect_note], but the note is relative to `tuning` instead of A4 = 440 Hz.
+/// ## Examples
+/// ```rust
+/// use pitch_detector::{
+///     core::{utils::sine_wave_signal, NoteName},
+///     note::{detect_note_with_tuning, Tuning},
+///     pitch::{HannedFftDetector, PitchDetector},
+/// };
+/// # fn example_detect_note() -> anyhow::Result<()> {
+/// # const NUM_SAMPLES: usize = 16384;
+/// # const SAMPLE_RATE: f64 = 44100.0;
+//
+/// let mut detector = HannedFftDetector::default();
+/// let baroque_a = 415.;
+/// let signal = sine_wave_signal(NUM_SAMPLES, baroque_a, SAMPLE_RATE);
+/// let note = detect_note_with_tuning(
+///     &signal,
+///     &mut detector,
+///     SAMPLE_RATE,
+///     &Tuning::from_a4_freq(415.),
+/// )?;
+//
+/// assert_eq!(note.note_name, NoteName::A);
+/// assert!(note.in_tune);
+/// # Ok(())
+/// # }
+/// ```
+pub fn detect_note_with_tuning<S: Sample, D: PitchDetector<S>>(
+    signal: &[S],
+    freq_detector: &mut D,
+    sample_rate: f64,
+    tuning: &Tuning,
 ) -> Result<NoteDetection, PitchError> {
     let nyquist_freq = sample_rate / 2.;
     let min_freq = 20.; // Conventional minimum frequency for human hearing
-    detect_note_in_range(signal, freq_detector, sample_rate, min_freq..nyquist_freq)
+    detect_note_in_range_with_tuning(
+        signal,
+        freq_detector,
+        sample_rate,
+        min_freq..nyquist_freq,
+        tuning,
+    )
 }
 
 /// Returns the predominant note of the given signal within the specified range.
@@ -85,13 +136,30 @@ pub fn detect_note<D: PitchDetector>(
 /// # Ok(())
 /// # }
 /// ```
-pub fn detect_note_in_range<D: PitchDetector>(
-    signal: &[f64],
+pub fn detect_note_in_range<S: Sample, D: PitchDetector<S>>(
+    signal: &[S],
+    freq_detector: &mut D,
+    sample_rate: f64,
+    freq_range: Range<f64>,
+) -> Result<NoteDetection, PitchError> {
+    detect_note_in_range_with_tuning(
+        signal,
+        freq_detector,
+        sample_rate,
+        freq_range,
+        &Tuning::default(),
+    )
+}
+
+/// Same as [detect_note_in_range], but the note is relative to `tuning` instead of A4 = 440 Hz.
+pub fn detect_note_in_range_with_tuning<S: Sample, D: PitchDetector<S>>(
+    signal: &[S],
     freq_detector: &mut D,
     sample_rate: f64,
     freq_range: Range<f64>,
+    tuning: &Tuning,
 ) -> Result<NoteDetection, PitchError> {
     freq_detector
-        .detect_pitch_in_range(signal, sample_rate, freq_range)
-        .and_then(|f| f.try_into())
+        .detect_with_confidence_in_range(signal, sample_rate, freq_range)
+        .and_then(|detection| tuning.to_note(detection))
 }
@@ -1,9 +1,10 @@
-use crate::core::{
-    constants::{A4_FREQ, MAX_CENTS_OFFSET, MIN_FREQ, NOTES},
-    error::PitchError,
-    NoteName,
+use crate::{
+    core::{error::PitchError, NoteName},
+    pitch::PitchDetection,
 };
 
+use super::Tuning;
+
 /// The resut of a pitch detection expressed as a note.
 /// You will rarely need to instantiate this struct directly. Most commonly this will be returned from
 /// [`detect_note`](crate::note::detect_note).
@@ -21,6 +22,10 @@ pub struct NoteDetection {
     /// The octave of the detected note.
     pub octave: i32,
 
+    /// The degree of the detected note in the scale of the [`Tuning`](crate::note::Tuning), starting at 0 for the
+    /// tonic. With the default tuning, this is the number of semitones above C.
+    pub scale_degree: usize,
+
     /// The degree to which the detected not is in tune, expressed in cents. The absolute maximum `cents_offset` is
     /// 50, since anything larger than 50 would be considered the next or previous note.
     pub cents_offset: f64,
@@ -34,37 +39,38 @@ pub struct NoteDetection {
     /// A `NoteDetectionResult` will be marked as `in_tune` if the `cents_offset` is less than
     /// [`MAX_CENTS_OFFSET`](crate::core::constants::MAX_CENTS_OFFSET).
     pub in_tune: bool,
+
+    /// How confident the detector is in the detected pitch, in the range [0, 1]. See
+    /// [`PitchDetection::confidence`](crate::pitch::PitchDetection::confidence). Always 0 for notes converted from a
+    /// bare frequency.
+    pub confidence: f64,
+
+    /// The root mean square level of the signal. See
+    /// [`PitchDetection::signal_level`](crate::pitch::PitchDetection::signal_level). Always 0 for notes converted
+    /// from a bare frequency.
+    pub signal_level: f64,
 }
 
+/// A bare frequency carries no information about the signal, so the `confidence` and `signal_level` of the resulting
+/// note are placeholders rather than measurements: the confidence and the signal level are always 0.
+/// Convert a [`PitchDetection`] instead to keep the values measured by a detector.
 impl TryFrom<f64> for NoteDetection {
     type Error = PitchError;
     fn try_from(freq: f64) -> Result<Self, Self::Error> {
-        if freq < MIN_FREQ {
-            return Err(PitchError::IncorrectParameters(format!(
-                "Invalid frequency: {}",
-                freq
-            )));
+        PitchDetection {
+            freq,
+            confidence: 0.,
+            signal_level: 0.,
         }
-        let steps_from_a4 = (freq / A4_FREQ).log2() * 12.0;
-        let note_freq = A4_FREQ * 2f64.powf(steps_from_a4.round() / 12.0);
-        let steps_from_c5 = steps_from_a4 - 2.0;
-        let cents_offset = (steps_from_a4 - steps_from_a4.round()) * 100.0;
-        Ok(Self {
-            actual_freq: freq,
-            note_name: NOTES
-                [(steps_from_a4.round() as isize).rem_euclid(NOTES.len() as isize) as usize]
-                .into(),
-            note_freq,
-            octave: (5. + (steps_from_c5 / 12.0).floor()) as i32,
-            cents_offset,
-            previous_note_name: NOTES
-                [(steps_from_a4.round() as isize - 1).rem_euclid(NOTES.len() as isize) as usize]
-                .into(),
-            next_note_name: NOTES
-                [(steps_from_a4.round() as isize + 1).rem_euclid(NOTES.len() as isize) as usize]
-                .into(),
-            in_tune: cents_offset.abs() < MAX_CENTS_OFFSET,
-        })
+        .try_into()
+    }
+}
+
+/// Uses the default [`Tuning`](crate::note::Tuning) of A4 = 440 Hz.
+impl TryFrom<PitchDetection> for NoteDetection {
+    type Error = PitchError;
+    fn try_from(detection: PitchDetection) -> Result<Self, Self::Error> {
+        Tuning::default().to_note(detection)
     }
 }
 
@@ -73,6 +79,7 @@ mod tests {
     use super::*;
     use anyhow::Result;
     use float_cmp::ApproxEq;
+    #[allow(clippy::too_many_arguments)]
     fn test_pitch_from_f64(
         actual_freq: f64,
         note_name: NoteName,
@@ -249,4 +256,25 @@ mod tests {
         .is_err());
         Ok(())
     }
+
+    #[test]
+    fn note_keeps_confidence_of_pitch_detection() -> Result<()> {
+        let note = NoteDetection::try_from(PitchDetection {
+            freq: 448.,
+            confidence: 0.8,
+            signal_level: 0.5,
+        })?;
+        assert_eq!(note.note_name, NoteName::A);
+        assert_eq!(note.confidence, 0.8);
+        assert_eq!(note.signal_level, 0.5);
+        Ok(())
+    }
+
+    #[test]
+    fn bare_frequencies_have_placeholder_confidence_and_level() -> Result<()> {
+        let note = NoteDetection::try_from(440.)?;
+        assert_eq!(note.confidence, 0.);
+        assert_eq!(note.signal_level, 0.);
+        Ok(())
+    }
 }
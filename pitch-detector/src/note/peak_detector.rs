@@ -1,9 +1,20 @@
 use crate::core::FrequencyBin;
 
 pub(crate) trait PeakDetector {
-    fn detect_peaks(&self, spectrum: &[f64]) -> Vec<FrequencyBin>;
+    /// Clears `peaks` and fills it with the peaks of the spectrum. Reusing `peaks` across calls avoids allocating once
+    /// it has grown to fit the peaks.
+    fn detect_peaks_into(&self, spectrum: &[f64], peaks: &mut Vec<FrequencyBin>);
+
+    fn detect_peaks(&self, spectrum: &[f64]) -> Vec<FrequencyBin> {
+        let mut peaks = vec![];
+        self.detect_peaks_into(spectrum, &mut peaks);
+        peaks
+    }
 }
 
+/// Finds the peaks that rise above a threshold of `sigmas` standard deviations over the mean of the spectrum. Each
+/// peak spans from the trough before it rises above the threshold to the trough after it falls below it. Follows the
+/// algorithm of the `peak_finder` crate.
 pub struct PeakFinderDetector {
     sigmas: f64,
 }
@@ -12,16 +23,56 @@ impl PeakFinderDetector {
     pub fn new(sigmas: f64) -> Self {
         Self { sigmas }
     }
+
+    fn threshold(&self, spectrum: &[f64]) -> f64 {
+        let mean = spectrum.iter().sum::<f64>() / spectrum.len() as f64;
+        let variance = spectrum
+            .iter()
+            .map(|value| (value - mean) * (value - mean))
+            .sum::<f64>()
+            / (spectrum.len() as f64 - 1.);
+        mean + self.sigmas * variance.sqrt()
+    }
 }
 
 impl PeakDetector for PeakFinderDetector {
-    fn detect_peaks(&self, spectrum: &[f64]) -> Vec<FrequencyBin> {
-        peak_finder::peaks::find_peaks_over_stddev(spectrum, self.sigmas)
-            .into_iter()
-            .map(|graph_peak| FrequencyBin {
-                bin: graph_peak.peak.x,
-                magnitude: graph_peak.peak.y,
-            })
-            .collect()
+    fn detect_peaks_into(&self, spectrum: &[f64], peaks: &mut Vec<FrequencyBin>) {
+        peaks.clear();
+        let threshold = self.threshold(spectrum);
+
+        // Bin 0 marks a point that has not been found yet
+        let unset = FrequencyBin::default();
+        let (mut left_trough, mut peak, mut right_trough) =
+            (unset.clone(), unset.clone(), unset.clone());
+        for (bin, &magnitude) in spectrum.iter().enumerate() {
+            let point = FrequencyBin { bin, magnitude };
+            if magnitude < threshold {
+                if right_trough.bin > 0 {
+                    if magnitude <= right_trough.magnitude {
+                        // Still descending
+                        right_trough = point;
+                    } else {
+                        // Rising again, so the peak is complete
+                        peaks.push(peak);
+                        (left_trough, peak, right_trough) =
+                            (unset.clone(), unset.clone(), unset.clone());
+                    }
+                } else if left_trough.bin > 0 && peak.bin > left_trough.bin {
+                    right_trough = point;
+                } else {
+                    left_trough = point;
+                }
+            } else if left_trough.bin > 0 {
+                if peak.bin == 0 || magnitude >= peak.magnitude {
+                    peak = point;
+                    right_trough = unset.clone();
+                }
+            } else if right_trough.bin > 0 {
+                peaks.push(peak);
+                (left_trough, peak, right_trough) = (unset.clone(), unset.clone(), unset.clone());
+            } else {
+                left_trough = point;
+            }
+        }
     }
 }
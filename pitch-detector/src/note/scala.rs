@@ -0,0 +1,554 @@
+//! Support for the tuning files of [Scala](https://www.huygens-fokker.org/scala/), which describe scales in `.scl`
+//! files and how they are laid out on a keyboard in `.kbm` files. Together they describe any tuning, including
+//! microtonal ones, and they can be loaded as a [`Tuning`](crate::note::Tuning).
+
+use std::{path::Path, str::FromStr};
+
+use crate::core::error::PitchError;
+
+use super::tuning::NUM_KEYS;
+
+fn incorrect(message: String) -> PitchError {
+    PitchError::IncorrectParameters(message)
+}
+
+/// Lines of a Scala file that are not comments.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .filter(|line| !line.starts_with('!'))
+        .map(|line| line.trim_end_matches('\r'))
+}
+
+fn first_token(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn parse_value<T: FromStr>(line: Option<&str>, name: &str) -> Result<T, PitchError> {
+    let line = line.ok_or_else(|| incorrect(format!("Missing {}", name)))?;
+    first_token(line)
+        .parse()
+        .map_err(|_| incorrect(format!("Invalid {}: {}", name, line.trim())))
+}
+
+fn read_file(path: &Path) -> Result<String, PitchError> {
+    std::fs::read_to_string(path)
+        .map_err(|e| incorrect(format!("Could not read {}: {}", path.display(), e)))
+}
+
+/// A scale from a `.scl` file. Every degree of the scale is an interval above the first degree, and the last degree
+/// is the period at which the scale repeats, usually an octave.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    description: String,
+
+    /// Cents of every degree but the first, which is always 0 cents
+    cents: Vec<f64>,
+}
+
+impl Scale {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PitchError> {
+        read_file(path.as_ref())?.parse()
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Number of degrees in each period of the scale.
+    pub fn num_degrees(&self) -> usize {
+        self.cents.len()
+    }
+
+    /// Interval at which the scale repeats, in cents.
+    pub fn period(&self) -> f64 {
+        self.cents[self.cents.len() - 1]
+    }
+
+    /// Cents of `degree` above the first degree. Degrees beyond the scale continue in the next periods, and negative
+    /// degrees in the previous ones.
+    pub fn degree_cents(&self, degree: i64) -> f64 {
+        let num_degrees = self.cents.len() as i64;
+        let periods = degree.div_euclid(num_degrees) as f64;
+        let cents = match degree.rem_euclid(num_degrees) {
+            0 => 0.,
+            idx => self.cents[idx as usize - 1],
+        };
+        periods * self.period() + cents
+    }
+
+    /// Parses a pitch, which is in cents if it has a period, and is a ratio otherwise.
+    fn parse_pitch(line: &str) -> Result<f64, PitchError> {
+        let token = first_token(line);
+        let invalid = || incorrect(format!("Invalid pitch: {}", line.trim()));
+        let cents = if token.contains('.') {
+            token.parse::<f64>().map_err(|_| invalid())?
+        } else {
+            let (numerator, denominator) = token.split_once('/').unwrap_or((token, "1"));
+            let numerator: u64 = numerator.parse().map_err(|_| invalid())?;
+            let denominator: u64 = denominator.parse().map_err(|_| invalid())?;
+            if numerator == 0 || denominator == 0 {
+                return Err(invalid());
+            }
+            1200. * (numerator as f64 / denominator as f64).log2()
+        };
+        if cents.is_finite() {
+            Ok(cents)
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl FromStr for Scale {
+    type Err = PitchError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = lines(text);
+        let description = lines
+            .next()
+            .ok_or_else(|| incorrect("Missing scale description".to_string()))?
+            .trim()
+            .to_string();
+        let num_degrees: usize = parse_value(lines.next(), "number of notes")?;
+        if num_degrees == 0 {
+            return Err(incorrect("Scale has no notes".to_string()));
+        }
+        let cents = (0..num_degrees)
+            .map(|_| {
+                let line = lines
+                    .next()
+                    .ok_or_else(|| incorrect(format!("Expected {} notes", num_degrees)))?;
+                Self::parse_pitch(line)
+            })
+            .collect::<Result<Vec<f64>, PitchError>>()?;
+        if cents[num_degrees - 1] <= 0. {
+            return Err(incorrect(format!(
+                "Scale period must be positive: {}",
+                cents[num_degrees - 1]
+            )));
+        }
+        Ok(Self { description, cents })
+    }
+}
+
+/// A keyboard mapping from a `.kbm` file, which maps keys to degrees of a [Scale] and sets the frequency of a
+/// reference key. Keys are MIDI note numbers, so 69 is A4 and 60 is C4.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    first_key: i32,
+    last_key: i32,
+
+    /// Key that is mapped to the first degree of the scale
+    middle_key: i32,
+
+    reference_key: i32,
+    reference_freq: f64,
+
+    /// Degree of the scale at which the mapping repeats. 0 repeats the mapping at the period of the scale
+    octave_degree: i64,
+
+    /// Degree of each key in a repetition of the mapping, starting at the middle key. Keys without a degree are not
+    /// mapped. If empty, every key is mapped to the next degree of the scale.
+    mapping: Vec<Option<i64>>,
+}
+
+impl KeyboardMapping {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PitchError> {
+        read_file(path.as_ref())?.parse()
+    }
+
+    pub fn middle_key(&self) -> i32 {
+        self.middle_key
+    }
+
+    pub fn reference_key(&self) -> i32 {
+        self.reference_key
+    }
+
+    pub fn reference_freq(&self) -> f64 {
+        self.reference_freq
+    }
+
+    /// Range of keys that are mapped to the scale.
+    pub fn keys(&self) -> std::ops::RangeInclusive<i32> {
+        self.first_key..=self.last_key
+    }
+
+    /// Cents of `key` above the middle key, and the degree of the scale that it is mapped to. `None` if the key is not
+    /// mapped.
+    fn key_cents(&self, scale: &Scale, key: i32) -> Option<(f64, i64)> {
+        let offset = (key - self.middle_key) as i64;
+        if self.mapping.is_empty() {
+            return Some((scale.degree_cents(offset), offset));
+        }
+        let len = self.mapping.len() as i64;
+        let degree = self.mapping[offset.rem_euclid(len) as usize]?;
+        let repetitions = offset.div_euclid(len) as f64;
+        let octave_cents = match self.octave_degree {
+            0 => scale.period(),
+            octave_degree => scale.degree_cents(octave_degree),
+        };
+        Some((
+            repetitions * octave_cents + scale.degree_cents(degree),
+            degree,
+        ))
+    }
+
+    /// Frequency of `key` in `scale`, and the degree of the scale that it is mapped to. `None` if the key is not
+    /// mapped.
+    pub fn key_freq(&self, scale: &Scale, key: i32) -> Option<(f64, usize)> {
+        if !self.keys().contains(&key) {
+            return None;
+        }
+        let (cents, degree) = self.key_cents(scale, key)?;
+        let (reference_cents, _) = self.key_cents(scale, self.reference_key)?;
+        let freq = self.reference_freq * 2f64.powf((cents - reference_cents) / 1200.);
+        Some((freq, degree.rem_euclid(scale.num_degrees() as i64) as usize))
+    }
+
+    /// Checks that the reference key can be tuned with `scale`.
+    pub(crate) fn validate(&self, scale: &Scale) -> Result<(), PitchError> {
+        if self.key_cents(scale, self.reference_key).is_none() {
+            return Err(incorrect(format!(
+                "Reference key {} is not mapped",
+                self.reference_key
+            )));
+        }
+        Ok(())
+    }
+
+    fn parse_key(line: Option<&str>, name: &str) -> Result<i32, PitchError> {
+        let key: i32 = parse_value(line, name)?;
+        if !(0..NUM_KEYS).contains(&key) {
+            return Err(incorrect(format!("Invalid {}: {}", name, key)));
+        }
+        Ok(key)
+    }
+}
+
+impl Default for KeyboardMapping {
+    /// Maps every key to the next degree of the scale, starting at C4, with A4 = 440 Hz. Matches the default mapping
+    /// of Scala.
+    fn default() -> Self {
+        Self {
+            first_key: 0,
+            last_key: NUM_KEYS - 1,
+            middle_key: 60,
+            reference_key: 69,
+            reference_freq: 440.,
+            octave_degree: 0,
+            mapping: vec![],
+        }
+    }
+}
+
+impl FromStr for KeyboardMapping {
+    type Err = PitchError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = lines(text).filter(|line| !line.trim().is_empty());
+        let map_size: usize = parse_value(lines.next(), "map size")?;
+        // A repetition of the mapping never needs to be longer than the keyboard
+        if map_size > NUM_KEYS as usize {
+            return Err(incorrect(format!("Invalid map size: {}", map_size)));
+        }
+        let first_key = Self::parse_key(lines.next(), "first key")?;
+        let last_key = Self::parse_key(lines.next(), "last key")?;
+        if first_key > last_key {
+            return Err(incorrect(format!(
+                "First key {} is after last key {}",
+                first_key, last_key
+            )));
+        }
+        let middle_key = Self::parse_key(lines.next(), "middle key")?;
+        let reference_key = Self::parse_key(lines.next(), "reference key")?;
+        let reference_freq: f64 = parse_value(lines.next(), "reference frequency")?;
+        if !(reference_freq.is_finite() && reference_freq > 0.) {
+            return Err(incorrect(format!(
+                "Invalid reference frequency: {}",
+                reference_freq
+            )));
+        }
+        let octave_degree: i64 = parse_value(lines.next(), "octave degree")?;
+        if octave_degree < 0 {
+            return Err(incorrect(format!(
+                "Invalid octave degree: {}",
+                octave_degree
+            )));
+        }
+        // Keys past the end of a short mapping are not mapped
+        let mut mapping = vec![];
+        for line in lines.by_ref().take(map_size) {
+            mapping.push(match first_token(line) {
+                "x" => None,
+                _ => {
+                    let degree: i64 = parse_value(Some(line), "scale degree")?;
+                    if degree < 0 {
+                        return Err(incorrect(format!("Invalid scale degree: {}", degree)));
+                    }
+                    Some(degree)
+                }
+            });
+        }
+        mapping.resize(map_size, None);
+        Ok(Self {
+            first_key,
+            last_key,
+            middle_key,
+            reference_key,
+            reference_freq,
+            octave_degree,
+            mapping,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::NoteName,
+        note::{Temperament, Tuning},
+    };
+    use float_cmp::ApproxEq;
+
+    const NOTE_NAMES: [NoteName; 12] = [
+        NoteName::A,
+        NoteName::ASharp,
+        NoteName::B,
+        NoteName::C,
+        NoteName::CSharp,
+        NoteName::D,
+        NoteName::DSharp,
+        NoteName::E,
+        NoteName::F,
+        NoteName::FSharp,
+        NoteName::G,
+        NoteName::GSharp,
+    ];
+
+    const MEANTONE: &str = "! meanquar.scl
+!
+1/4-comma meantone scale. Pietro Aaron's temperament (1523)
+ 12
+!
+ 76.04900
+ 193.15686
+ 310.26471
+ 5/4
+ 503.42157
+ 579.47057
+ 696.57843
+ 25/16
+ 889.73529
+ 1006.84314
+ 1082.89214
+ 2/1
+";
+
+    #[test]
+    fn it_parses_scales() -> anyhow::Result<()> {
+        let scale: Scale = MEANTONE.parse()?;
+        assert_eq!(
+            scale.description(),
+            "1/4-comma meantone scale. Pietro Aaron's temperament (1523)"
+        );
+        assert_eq!(scale.num_degrees(), 12);
+        assert!(scale.period().approx_eq(1200., (1e-9, 2)));
+        assert!(scale.degree_cents(4).approx_eq(386.314, (0.001, 2)));
+        assert!(scale.degree_cents(16).approx_eq(1586.314, (0.001, 2)));
+        assert!(scale.degree_cents(-8).approx_eq(-813.686, (0.001, 2)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_keyboard_mappings() -> anyhow::Result<()> {
+        let mapping: KeyboardMapping = "! Every other key is mapped
+4
+0
+127
+60
+69
+442.0
+2
+! Mapping
+0
+x
+1
+x
+"
+        .parse()?;
+        assert_eq!(mapping.keys(), 0..=127);
+        assert_eq!(mapping.reference_key(), 69);
+        assert_eq!(mapping.reference_freq(), 442.);
+        assert_eq!(
+            mapping.mapping,
+            vec![Some(0), None, Some(1), None],
+            "{:?}",
+            mapping
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_tunes_like_the_equivalent_temperament() -> anyhow::Result<()> {
+        let scala = Tuning::from_scala(MEANTONE.parse()?, KeyboardMapping::default())?;
+        let meantone = Tuning::default().with_temperament(Temperament::QuarterCommaMeantone);
+        for octave in 1..=6 {
+            for note_name in NOTE_NAMES {
+                let expected_freq = meantone.note_freq(note_name, octave).unwrap();
+                let freq = scala.note_freq(note_name, octave).unwrap();
+                assert!(
+                    freq.approx_eq(expected_freq, (1e-4, 2)),
+                    "{}{}",
+                    note_name,
+                    octave
+                );
+            }
+        }
+        let note = scala.freq_to_note(440. * 5. / 4.)?;
+        assert_eq!(note.note_name, NoteName::CSharp);
+        assert_eq!(note.octave, 5);
+        assert_eq!(note.scale_degree, 1);
+        assert!(note.cents_offset.abs() < 1e-3);
+        Ok(())
+    }
+
+    #[test]
+    fn it_finds_the_nearest_degree_of_microtonal_scales() -> anyhow::Result<()> {
+        // 19 equal divisions of the octave, with every key mapped to the next degree and C4 as the first degree
+        let edo19 = (1..=19)
+            .map(|degree| format!("{:.5}\n", 1200. * degree as f64 / 19.))
+            .collect::<String>();
+        let scale: Scale = format!("19-EDO\n19\n{}", edo19).parse()?;
+        let mapping: KeyboardMapping = "0\n0\n127\n60\n60\n261.6255653\n19\n".parse()?;
+        let tuning = Tuning::from_scala(scale, mapping)?;
+        let step = 2f64.powf(1. / 19.);
+        for degree in 0..19 {
+            let freq = 261.6255653 * step.powi(degree);
+            // A sixth of a step sharp
+            let note = tuning.freq_to_note(freq * step.powf(1. / 6.))?;
+            assert_eq!(note.scale_degree, degree as usize);
+            assert!(note.note_freq.approx_eq(freq, (1e-4, 2)));
+            assert!(note.cents_offset.approx_eq(1200. / 19. / 6., (1e-3, 2)));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_skips_unmapped_keys() -> anyhow::Result<()> {
+        // Only the white keys are mapped, to a 7 note just major scale
+        let scale: Scale = "Just major\n7\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n".parse()?;
+        let mapping: KeyboardMapping =
+            "12\n0\n127\n60\n69\n440.0\n7\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n".parse()?;
+        let tuning = Tuning::from_scala(scale, mapping)?;
+        assert_eq!(tuning.note_freq(NoteName::CSharp, 4), None);
+        assert!(tuning
+            .note_freq(NoteName::C, 4)
+            .unwrap()
+            .approx_eq(440. * 3. / 5., (1e-9, 2)));
+        let note = tuning.freq_to_note(tuning.note_freq(NoteName::E, 4).unwrap() * 1.03)?;
+        assert_eq!(note.note_name, NoteName::E);
+        assert_eq!(note.scale_degree, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn it_repeats_at_the_period_if_the_octave_degree_is_0() -> anyhow::Result<()> {
+        let scale: Scale = MEANTONE.parse()?;
+        let mapping: KeyboardMapping =
+            "12\n0\n127\n60\n69\n440.0\n0\n0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n".parse()?;
+        for (key, expected_freq) in [(57, 220.), (69, 440.), (81, 880.)] {
+            let (freq, degree) = mapping.key_freq(&scale, key).unwrap();
+            assert!(
+                freq.approx_eq(expected_freq, (1e-9, 2)),
+                "{}: {}",
+                key,
+                freq
+            );
+            assert_eq!(degree, 9);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_unmapped_reference_keys() -> anyhow::Result<()> {
+        let mapping: KeyboardMapping = "2\n0\n127\n60\n69\n440.0\n12\n0\nx\n".parse()?;
+        assert!(matches!(
+            Tuning::from_scala(MEANTONE.parse()?, mapping),
+            Err(PitchError::IncorrectParameters(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_malformed_scales() {
+        for text in [
+            "",
+            "! Only comments",
+            "Missing count",
+            "Bad count\nthree\n",
+            "Negative count\n-3\n",
+            "Empty\n0\n",
+            "Too few notes\n3\n100.0\n200.0\n",
+            "Bad pitch\n1\nabc\n",
+            "Bad ratio\n1\n3/\n",
+            "Zero ratio\n1\n0/1\n",
+            "Negative ratio\n1\n-3/2\n",
+            "Zero denominator\n1\n3/0\n",
+            "Infinite cents\n1\n1.0e999\n",
+            "Empty pitch\n1\n\n",
+            "Non-positive period\n2\n100.0\n0.0\n",
+            "Huge ratio\n1\n99999999999999999999999/1\n",
+        ] {
+            assert!(
+                matches!(
+                    text.parse::<Scale>(),
+                    Err(PitchError::IncorrectParameters(_))
+                ),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn it_rejects_malformed_keyboard_mappings() {
+        for text in [
+            "",
+            "12\n0\n127\n60\n69\n",
+            "12\n0\n128\n60\n69\n440.0\n12\n",
+            "12\n-1\n127\n60\n69\n440.0\n12\n",
+            "12\n100\n10\n60\n69\n440.0\n12\n",
+            "12\n0\n127\n60\n69\n0.0\n12\n",
+            "12\n0\n127\n60\n69\nNaN\n12\n",
+            "12\n0\n127\n60\n69\n440.0\n-1\n",
+            "1\n0\n127\n60\n69\n440.0\n12\ny\n",
+            "1\n0\n127\n60\n69\n440.0\n12\n-1\n",
+            "many\n0\n127\n60\n69\n440.0\n12\n",
+            "129\n0\n127\n60\n69\n440.0\n12\n",
+            "18446744073709551615\n0\n127\n60\n69\n440.0\n12\n",
+        ] {
+            assert!(
+                matches!(
+                    text.parse::<KeyboardMapping>(),
+                    Err(PitchError::IncorrectParameters(_))
+                ),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn it_reports_unreadable_files() {
+        assert!(matches!(
+            Scale::from_file("does_not_exist.scl"),
+            Err(PitchError::IncorrectParameters(_))
+        ));
+        assert!(matches!(
+            KeyboardMapping::from_file("does_not_exist.kbm"),
+            Err(PitchError::IncorrectParameters(_))
+        ));
+    }
+}
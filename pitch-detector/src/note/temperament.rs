@@ -0,0 +1,133 @@
+/// How the twelve notes of the octave are tuned relative to each other. Equal temperament, the default, splits the
+/// octave in twelve equal semitones. Historical temperaments tune some intervals purer than others, so the notes
+/// closer to the tonic of a [`Tuning`](crate::note::Tuning) sound better, at the expense of the more remote ones.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Temperament {
+    #[default]
+    Equal,
+
+    /// 5-limit just intonation, with pure fifths and thirds above the tonic.
+    Just,
+
+    /// Every fifth is pure, except for the wolf fifth between G# and Eb when the tonic is C.
+    Pythagorean,
+
+    /// Quarter-comma meantone, with pure major thirds and the wolf fifth between G# and Eb when the tonic is C.
+    QuarterCommaMeantone,
+
+    /// Werckmeister III, a well temperament where the fifths C-G-D-A and B-F# are narrowed by a quarter of the
+    /// Pythagorean comma.
+    WerckmeisterIII,
+
+    /// Vallotti, a well temperament where the fifths F-C-G-D-A-E-B are narrowed by a sixth of the Pythagorean comma.
+    Vallotti,
+}
+
+fn ratio_to_cents(ratio: f64) -> f64 {
+    1200. * ratio.log2()
+}
+
+impl Temperament {
+    /// Cents of each of the twelve notes above the tonic, starting with the tonic itself.
+    pub fn cents(&self) -> [f64; 12] {
+        match self {
+            Temperament::Equal => std::array::from_fn(|degree| 100. * degree as f64),
+            Temperament::Just => [
+                1.,
+                16. / 15.,
+                9. / 8.,
+                6. / 5.,
+                5. / 4.,
+                4. / 3.,
+                45. / 32.,
+                3. / 2.,
+                8. / 5.,
+                5. / 3.,
+                9. / 5.,
+                15. / 8.,
+            ]
+            .map(ratio_to_cents),
+            Temperament::Pythagorean => [
+                1.,
+                256. / 243.,
+                9. / 8.,
+                32. / 27.,
+                81. / 64.,
+                4. / 3.,
+                729. / 512.,
+                3. / 2.,
+                128. / 81.,
+                27. / 16.,
+                16. / 9.,
+                243. / 128.,
+            ]
+            .map(ratio_to_cents),
+            Temperament::QuarterCommaMeantone => {
+                // A chain of fifths from Eb to G#, each narrowed so that four of them make a pure major third
+                let fifth = ratio_to_cents(5f64.powf(0.25));
+                let mut cents = [0.; 12];
+                for fifths in -3i32..=8 {
+                    cents[(7 * fifths).rem_euclid(12) as usize] =
+                        (fifths as f64 * fifth).rem_euclid(1200.);
+                }
+                cents
+            }
+            Temperament::WerckmeisterIII => [
+                0., 90.225, 192.180, 294.135, 390.225, 498.045, 588.270, 696.090, 792.180, 888.270,
+                996.090, 1092.180,
+            ],
+            Temperament::Vallotti => [
+                0., 94.135, 196.090, 298.045, 392.180, 501.955, 592.180, 698.045, 796.090, 894.135,
+                1000., 1090.225,
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::ApproxEq;
+
+    const TEMPERAMENTS: [Temperament; 6] = [
+        Temperament::Equal,
+        Temperament::Just,
+        Temperament::Pythagorean,
+        Temperament::QuarterCommaMeantone,
+        Temperament::WerckmeisterIII,
+        Temperament::Vallotti,
+    ];
+
+    #[test]
+    fn notes_ascend_within_the_octave() {
+        for temperament in TEMPERAMENTS {
+            let cents = temperament.cents();
+            assert_eq!(cents[0], 0., "{:?}", temperament);
+            for (degree, pair) in cents.windows(2).enumerate() {
+                assert!(pair[0] < pair[1], "{:?}: {:?}", temperament, cents);
+                // No note strays more than a quarter tone from equal temperament
+                assert!(
+                    (pair[1] - 100. * (degree + 1) as f64).abs() < 50.,
+                    "{:?}: {:?}",
+                    temperament,
+                    cents
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_has_the_characteristic_intervals() {
+        let pure_fifth = ratio_to_cents(1.5);
+        let pure_major_third = ratio_to_cents(1.25);
+        assert!(Temperament::Just.cents()[7].approx_eq(pure_fifth, (1e-9, 2)));
+        assert!(Temperament::Just.cents()[4].approx_eq(pure_major_third, (1e-9, 2)));
+        assert!(Temperament::Pythagorean.cents()[7].approx_eq(pure_fifth, (1e-9, 2)));
+        assert!(Temperament::Pythagorean.cents()[2].approx_eq(203.91, (0.01, 2)));
+        assert!(Temperament::QuarterCommaMeantone.cents()[4].approx_eq(pure_major_third, (1e-9, 2)));
+        assert!(Temperament::QuarterCommaMeantone.cents()[7].approx_eq(696.578, (0.001, 2)));
+        assert!(Temperament::QuarterCommaMeantone.cents()[3].approx_eq(310.265, (0.001, 2)));
+        assert!(Temperament::WerckmeisterIII.cents()[7].approx_eq(696.09, (1e-9, 2)));
+        assert!(Temperament::Vallotti.cents()[10].approx_eq(1000., (1e-9, 2)));
+    }
+}
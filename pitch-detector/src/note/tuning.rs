@@ -0,0 +1,387 @@
+use crate::{
+    core::{
+        constants::{A4_FREQ, MAX_CENTS_OFFSET, MIN_FREQ, NOTES},
+        error::PitchError,
+        NoteName,
+    },
+    pitch::PitchDetection,
+};
+
+use super::{KeyboardMapping, NoteDetection, Scale, Temperament};
+
+/// MIDI note number of A4
+pub(crate) const A4_KEY: i32 = 69;
+
+/// Number of MIDI notes
+pub(crate) const NUM_KEYS: i32 = 128;
+
+fn note_name(step: i32) -> NoteName {
+    NoteName::from(NOTES[step.rem_euclid(NOTES.len() as i32) as usize])
+}
+
+/// Octave of the note that is `step` semitones from A4. Octaves start at C, which is 9 semitones below A4.
+fn octave(step: i32) -> i32 {
+    4 + (step + 9).div_euclid(12)
+}
+
+/// The frequency that a reference note is tuned to, from which the frequencies of every other note follow. Most
+/// music is tuned to A4 = 440 Hz, which is the default, but orchestras often tune a little higher, such as 442 Hz,
+/// and baroque ensembles tune to 415 Hz.
+///
+/// The other notes are tuned relative to the tonic with the [Temperament] of the tuning, which is equal temperament
+/// by default. The tonic only matters for the other temperaments.
+///
+/// Alternatively, a tuning can be loaded from the [Scale] and [KeyboardMapping] of a Scala tuning, which can tune any
+/// key of the keyboard to any frequency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    reference_note: NoteName,
+    reference_octave: i32,
+    reference_freq: f64,
+    temperament: Temperament,
+    tonic: NoteName,
+
+    /// Scala tuning, which takes precedence over the temperament
+    scala: Option<(Scale, KeyboardMapping)>,
+}
+
+impl Tuning {
+    pub fn new(reference_note: NoteName, reference_octave: i32, reference_freq: f64) -> Self {
+        Self {
+            reference_note,
+            reference_octave,
+            reference_freq,
+            temperament: Temperament::Equal,
+            tonic: NoteName::C,
+            scala: None,
+        }
+    }
+
+    /// Tuning of a Scala scale laid out on the keyboard by `mapping`. The reference note and frequency come from the
+    /// mapping, and the tonic is the note of its middle key. The temperament is ignored. Each note is the key of the
+    /// keyboard that it is mapped to, so microtonal scales with more than twelve notes per octave reuse note names.
+    pub fn from_scala(scale: Scale, mapping: KeyboardMapping) -> Result<Self, PitchError> {
+        mapping.validate(&scale)?;
+        let reference_step = mapping.reference_key() - A4_KEY;
+        Ok(Self {
+            reference_note: note_name(reference_step),
+            reference_octave: octave(reference_step),
+            reference_freq: mapping.reference_freq(),
+            temperament: Temperament::Equal,
+            tonic: note_name(mapping.middle_key() - A4_KEY),
+            scala: Some((scale, mapping)),
+        })
+    }
+
+    pub fn with_temperament(self, temperament: Temperament) -> Self {
+        Self {
+            temperament,
+            ..self
+        }
+    }
+
+    pub fn with_tonic(self, tonic: NoteName) -> Self {
+        Self { tonic, ..self }
+    }
+
+    /// Tuning with A4 as the reference note.
+    pub fn from_a4_freq(a4_freq: f64) -> Self {
+        Self::new(NoteName::A, 4, a4_freq)
+    }
+
+    pub fn reference_note(&self) -> NoteName {
+        self.reference_note
+    }
+
+    pub fn reference_octave(&self) -> i32 {
+        self.reference_octave
+    }
+
+    pub fn reference_freq(&self) -> f64 {
+        self.reference_freq
+    }
+
+    pub fn temperament(&self) -> &Temperament {
+        &self.temperament
+    }
+
+    pub fn tonic(&self) -> NoteName {
+        self.tonic
+    }
+
+    /// Number of semitones from A4 to the note.
+    fn steps_from_a4(note_name: NoteName, octave: i32) -> i32 {
+        let idx = note_name as i32;
+        let steps_in_octave = if idx >= NoteName::C as i32 {
+            idx - 12
+        } else {
+            idx
+        };
+        steps_in_octave + 12 * (octave - 4)
+    }
+
+    /// Cents from the tonic of the fourth octave to the note that is `step` semitones from A4, in the temperament of this tuning.
+    fn step_cents(&self, step: i32, cents: &[f64; 12]) -> f64 {
+        let steps_from_tonic = step - Self::steps_from_a4(self.tonic, 4);
+        cents[steps_from_tonic.rem_euclid(12) as usize]
+            + 1200. * steps_from_tonic.div_euclid(12) as f64
+    }
+
+    /// Frequency of the note that is `step` semitones from A4, and its degree in the scale. `None` if a Scala keyboard
+    /// mapping doesn't map the note.
+    fn step_note(&self, step: i32) -> Option<(f64, usize)> {
+        if let Some((scale, mapping)) = &self.scala {
+            return mapping.key_freq(scale, A4_KEY + step);
+        }
+        let cents = self.temperament.cents();
+        let reference_step = Self::steps_from_a4(self.reference_note, self.reference_octave);
+        let freq = self.reference_freq
+            * 2f64.powf(
+                (self.step_cents(step, &cents) - self.step_cents(reference_step, &cents)) / 1200.,
+            );
+        let degree = (step - Self::steps_from_a4(self.tonic, 4)).rem_euclid(12) as usize;
+        Some((freq, degree))
+    }
+
+    /// Frequency of the note that is `step` semitones from A4.
+    pub(crate) fn step_freq(&self, step: i32) -> Option<f64> {
+        self.step_note(step).map(|(freq, _)| freq)
+    }
+
+    /// Frequency of the note in this tuning. `None` if a Scala keyboard mapping doesn't map the note.
+    pub fn note_freq(&self, note_name: NoteName, octave: i32) -> Option<f64> {
+        self.step_freq(Self::steps_from_a4(note_name, octave))
+    }
+
+    /// Finds the closest note to the detected pitch, and how far off it is.
+    pub fn to_note(&self, detection: PitchDetection) -> Result<NoteDetection, PitchError> {
+        if !(self.reference_freq.is_finite() && self.reference_freq > 0.) {
+            return Err(PitchError::IncorrectParameters(format!(
+                "Invalid reference frequency: {}",
+                self.reference_freq
+            )));
+        }
+        let freq = detection.freq;
+        if freq < MIN_FREQ {
+            return Err(PitchError::IncorrectParameters(format!(
+                "Invalid frequency: {}",
+                freq
+            )));
+        }
+        let steps = match &self.scala {
+            // Scala tunings can be anything, so every key is a candidate
+            Some((_, mapping)) => {
+                let keys = mapping.keys();
+                keys.start() - A4_KEY..=keys.end() - A4_KEY
+            }
+            // Temperaments stray less than a quarter tone from equal temperament, so the closest note is at most one
+            // step away from the closest note in equal temperament
+            None => {
+                let a4_freq = self.step_freq(0).expect("Temperaments tune every note");
+                let equal_step = ((freq / a4_freq).log2() * 12.0).round() as i32;
+                equal_step - 1..=equal_step + 1
+            }
+        };
+        let (step, note_freq, scale_degree, cents_offset) = steps
+            .filter_map(|step| {
+                let (note_freq, scale_degree) = self.step_note(step)?;
+                Some((
+                    step,
+                    note_freq,
+                    scale_degree,
+                    (freq / note_freq).log2() * 1200.,
+                ))
+            })
+            .min_by(|a, b| a.3.abs().total_cmp(&b.3.abs()))
+            .ok_or_else(|| {
+                PitchError::IncorrectParameters("The tuning doesn't map any note".to_string())
+            })?;
+        Ok(NoteDetection {
+            actual_freq: freq,
+            note_name: note_name(step),
+            note_freq,
+            octave: octave(step),
+            scale_degree,
+            cents_offset,
+            previous_note_name: note_name(step - 1),
+            next_note_name: note_name(step + 1),
+            in_tune: cents_offset.abs() < MAX_CENTS_OFFSET,
+            confidence: detection.confidence,
+            signal_level: detection.signal_level,
+        })
+    }
+
+    /// Same as [Tuning::to_note], for a bare frequency. The resulting note has a confidence and a signal level of 0.
+    pub fn freq_to_note(&self, freq: f64) -> Result<NoteDetection, PitchError> {
+        self.to_note(PitchDetection {
+            freq,
+            confidence: 0.,
+            signal_level: 0.,
+        })
+    }
+}
+
+impl Default for Tuning {
+    /// A4 = 440 Hz
+    fn default() -> Self {
+        Self::from_a4_freq(A4_FREQ)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::ApproxEq;
+
+    fn detection(freq: f64) -> PitchDetection {
+        PitchDetection {
+            freq,
+            confidence: 1.,
+            signal_level: 0.,
+        }
+    }
+
+    const NOTE_NAMES: [NoteName; 12] = [
+        NoteName::A,
+        NoteName::ASharp,
+        NoteName::B,
+        NoteName::C,
+        NoteName::CSharp,
+        NoteName::D,
+        NoteName::DSharp,
+        NoteName::E,
+        NoteName::F,
+        NoteName::FSharp,
+        NoteName::G,
+        NoteName::GSharp,
+    ];
+
+    #[test]
+    fn notes_round_trip_at_reference_pitches() -> anyhow::Result<()> {
+        for a4_freq in [415., 432., 440., 442.] {
+            let tuning = Tuning::from_a4_freq(a4_freq);
+            assert!(tuning
+                .note_freq(NoteName::A, 4)
+                .unwrap()
+                .approx_eq(a4_freq, (1e-9, 2)));
+            for octave in 1..=6 {
+                for note_name in NOTE_NAMES {
+                    let freq = tuning.note_freq(note_name, octave).unwrap();
+                    if freq < MIN_FREQ {
+                        continue;
+                    }
+                    let note = tuning.to_note(detection(freq))?;
+                    assert_eq!(note.note_name, note_name, "{} Hz at A4 = {}", freq, a4_freq);
+                    assert_eq!(note.octave, octave, "{} Hz at A4 = {}", freq, a4_freq);
+                    assert!(note.note_freq.approx_eq(freq, (1e-9, 2)));
+                    assert!(note.cents_offset.abs() < 1e-9);
+                    assert!(note.in_tune);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn notes_round_trip_in_every_temperament() -> anyhow::Result<()> {
+        for temperament in [
+            Temperament::Equal,
+            Temperament::Just,
+            Temperament::Pythagorean,
+            Temperament::QuarterCommaMeantone,
+            Temperament::WerckmeisterIII,
+            Temperament::Vallotti,
+        ] {
+            for tonic in NOTE_NAMES {
+                let tuning = Tuning::from_a4_freq(415.)
+                    .with_temperament(temperament.clone())
+                    .with_tonic(tonic);
+                assert!(tuning
+                    .note_freq(NoteName::A, 4)
+                    .unwrap()
+                    .approx_eq(415., (1e-9, 2)));
+                for octave in 2..=5 {
+                    for note_name in NOTE_NAMES {
+                        let freq = tuning.note_freq(note_name, octave).unwrap();
+                        let note = tuning.freq_to_note(freq)?;
+                        assert_eq!(
+                            note.note_name, note_name,
+                            "{:?} in {:?}",
+                            temperament, tonic
+                        );
+                        assert_eq!(note.octave, octave, "{:?} in {:?}", temperament, tonic);
+                        assert!(note.cents_offset.abs() < 1e-9);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cents_offset_depends_on_the_temperament() -> anyhow::Result<()> {
+        let equal = Tuning::default();
+        let just_in_a = Tuning::default()
+            .with_temperament(Temperament::Just)
+            .with_tonic(NoteName::A);
+        let just_in_c = just_in_a.clone().with_tonic(NoteName::C);
+
+        // The major third above the tonic is pure in just intonation, and about 14 cents narrower than in equal
+        // temperament
+        let just_major_third = 440. * 5. / 4.;
+        let note = just_in_a.freq_to_note(just_major_third)?;
+        assert_eq!(note.note_name, NoteName::CSharp);
+        assert!(note.note_freq.approx_eq(just_major_third, (1e-9, 2)));
+        assert!(note.cents_offset.abs() < 1e-9);
+        let note = equal.freq_to_note(just_major_third)?;
+        assert_eq!(note.note_name, NoteName::CSharp);
+        assert!(note.cents_offset.approx_eq(-13.686, (0.001, 2)));
+
+        // The same note is somewhere else relative to another tonic
+        let note = just_in_c.freq_to_note(just_major_third)?;
+        assert_eq!(note.note_name, NoteName::CSharp);
+        assert!(note.cents_offset.abs() > 1.);
+        Ok(())
+    }
+
+    #[test]
+    fn it_tunes_to_any_reference_note() {
+        let tuning = Tuning::new(NoteName::C, 4, 256.);
+        assert!(tuning
+            .note_freq(NoteName::C, 4)
+            .unwrap()
+            .approx_eq(256., (1e-9, 2)));
+        assert!(tuning
+            .note_freq(NoteName::C, 5)
+            .unwrap()
+            .approx_eq(512., (1e-9, 2)));
+        assert!(tuning
+            .note_freq(NoteName::A, 4)
+            .unwrap()
+            .approx_eq(256. * 2f64.powf(9. / 12.), (1e-9, 2)));
+    }
+
+    #[test]
+    fn cents_offset_depends_on_the_reference_pitch() -> anyhow::Result<()> {
+        let detection = detection(442.);
+        let note = Tuning::default().to_note(detection.clone())?;
+        assert_eq!(note.note_name, NoteName::A);
+        assert_eq!(note.scale_degree, 9);
+        assert!(note.cents_offset.approx_eq(7.85, (0.01, 2)));
+        let note = Tuning::from_a4_freq(442.).to_note(detection)?;
+        assert_eq!(note.note_name, NoteName::A);
+        assert!(note.cents_offset.abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_invalid_reference_frequencies() {
+        let detection = detection(440.);
+        for a4_freq in [0., -440., f64::NAN] {
+            assert!(matches!(
+                Tuning::from_a4_freq(a4_freq).to_note(detection.clone()),
+                Err(PitchError::IncorrectParameters(_))
+            ));
+        }
+    }
+}
@@ -1,76 +1,132 @@
 // Rust implementation of Yin pitch detection algorithm
-use ndarray::Array1;
+use rustfft::num_complex::Complex;
 
-use crate::core::error::PitchError;
+use crate::core::{
+    error::PitchError, fft_space::FftSpace, sample::Sample, utils::parabolic_interpolation,
+    FftPoint,
+};
 
-use super::PitchDetector;
+use super::{PitchDetection, PitchDetector};
 
-/// Function to compute the Yin difference function.
-fn difference_function(signal: &[f64], max_lag: usize) -> Array1<f64> {
-    let mut diff = Array1::zeros(max_lag);
+/// Function to compute the Yin difference function into `diff`.
+///
+/// The difference function is expanded as `d(tau) = e_head(tau) + e_tail(tau) - 2 * r(tau)`, where `r` is the
+/// autocorrelation of the signal and `e_head`/`e_tail` are the energies of the overlapping parts of the signal.
+/// The autocorrelation is computed with an FFT, so `fft_space` needs to fit at least `signal.len() + max_lag`
+/// samples to avoid circular overlap.
+pub(super) fn difference_function(
+    fft_space: &mut FftSpace,
+    signal: &[f64],
+    max_lag: usize,
+    diff: &mut Vec<f64>,
+) {
+    let max_lag = max_lag.min(signal.len());
+    diff.clear();
+    diff.resize(max_lag, 0.0);
 
+    fft_space.init_with_signal(signal.iter());
+    fft_space.forward_fft();
+    fft_space.map(|f| Complex::new(f.norm_sqr(), 0.0));
+    fft_space.inverse_fft();
+    let normalize = 1.0 / fft_space.padded_len() as f64;
+    let acf = fft_space.space();
+
+    let energy: f64 = signal.iter().map(|x| x * x).sum();
+    let mut energy_head = energy;
+    let mut energy_tail = energy;
     for tau in 1..max_lag {
-        let mut sum = 0.0;
-        for i in 0..(signal.len() - tau) {
-            let delta = signal[i] - signal[i + tau];
-            sum += delta * delta;
-        }
-        diff[tau] = sum;
+        energy_head -= signal[signal.len() - tau] * signal[signal.len() - tau];
+        energy_tail -= signal[tau - 1] * signal[tau - 1];
+        diff[tau] = (energy_head + energy_tail - 2.0 * acf[tau].re * normalize).max(0.0);
     }
-
-    diff
 }
 
-/// Cumulative mean normalized difference function.
-fn cumulative_mean_normalized_difference(diff: Array1<f64>) -> Array1<f64> {
-    let mut cmnd = Array1::zeros(diff.len());
-    cmnd[0] = 1.0; // first value is typically set to 1.0 to avoid division by 0
+/// Cumulative mean normalized difference function, computed in place from the difference function.
+pub(super) fn cumulative_mean_normalized_difference(diff: &mut [f64]) {
+    if let Some(first) = diff.first_mut() {
+        *first = 1.0; // first value is typically set to 1.0 to avoid division by 0
+    }
 
     let mut running_sum = 0.0;
-    for tau in 1..diff.len() {
-        running_sum += diff[tau];
-        cmnd[tau] = diff[tau] / (running_sum / tau as f64);
+    for (tau, value) in diff.iter_mut().enumerate().skip(1) {
+        running_sum += *value;
+        // A silent signal has no difference at any lag, and is treated as aperiodic
+        *value = if running_sum > 0.0 {
+            *value / (running_sum / tau as f64)
+        } else {
+            1.0
+        };
     }
-
-    cmnd
 }
 
-/// Finds the pitch period (tau) given the cumulative mean normalized difference function.
-fn find_pitch_period(cmnd: &Array1<f64>, threshold: f64) -> Result<usize, PitchError> {
-    for tau in 1..cmnd.len() {
+/// Finds the pitch period (tau) given the cumulative mean normalized difference function. The chosen tau is the
+/// first local minimum below `threshold`. If no dip crosses the threshold, the global minimum is chosen instead.
+/// Only lags starting at `min_lag` are considered.
+fn find_pitch_period(cmnd: &[f64], threshold: f64, min_lag: usize) -> Result<usize, PitchError> {
+    let min_lag = min_lag.max(1);
+    let mut tau = min_lag;
+    while tau < cmnd.len() {
         if cmnd[tau] < threshold {
-            // Check if it's a minimum compared to the neighboring values
-            if tau + 1 < cmnd.len() && cmnd[tau] < cmnd[tau + 1] {
-                return Ok(tau);
+            // Follow the dip down to its minimum
+            while tau + 1 < cmnd.len() && cmnd[tau + 1] < cmnd[tau] {
+                tau += 1;
             }
+            return Ok(tau);
         }
+        tau += 1;
     }
-    Err(PitchError::NoPitchDetected(
-        "Did not find a pitch within threshol".to_string(),
-    ))
+
+    (min_lag..cmnd.len())
+        .reduce(|best, tau| if cmnd[tau] < cmnd[best] { tau } else { best })
+        .ok_or_else(|| {
+            PitchError::IncorrectParameters(
+                "Signal is too short for the requested frequency range".to_string(),
+            )
+        })
 }
 
-/// Main Yin pitch detection function
+/// Result of a YIN pitch estimation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct YinEstimate {
+    /// The estimated frequency in hertz.
+    pub freq: f64,
+
+    /// The value of the cumulative mean normalized difference function at the chosen period. Values close to 0
+    /// indicate a strongly periodic signal, while values around 1 or above indicate an aperiodic (noisy) signal.
+    pub aperiodicity: f64,
+}
+
+/// Main Yin pitch detection function. The cumulative mean normalized difference function is computed into `cmnd`, so
+/// that it can be reused across calls.
 pub fn yin_pitch(
+    fft_space: &mut FftSpace,
+    cmnd: &mut Vec<f64>,
     signal: &[f64],
     sample_rate: f64,
     threshold: f64,
+    min_lag: usize,
     max_lag: usize,
-) -> Result<f64, PitchError> {
+) -> Result<YinEstimate, PitchError> {
     // Step 1: Compute the difference function
-    let diff = difference_function(signal, max_lag);
+    difference_function(fft_space, signal, max_lag, cmnd);
 
     // Step 2: Compute the cumulative mean normalized difference function
-    let cmnd = cumulative_mean_normalized_difference(diff);
+    cumulative_mean_normalized_difference(cmnd);
 
-    // Step 3: Find the pitch period (tau) that meets the threshold
-    if let Ok(tau) = find_pitch_period(&cmnd, threshold) {
-        // Convert tau to frequency (pitch)
-        let pitch = sample_rate / tau as f64;
-        return Ok(pitch);
-    }
+    // Step 3: Find the pitch period (tau) that meets the threshold, or the best one if none does
+    let tau = find_pitch_period(cmnd, threshold, min_lag)?;
 
-    Err(PitchError::NoPitchDetected("No pitch detected".to_string()))
+    // Step 4: Refine tau to sub-sample precision
+    let FftPoint {
+        x: tau,
+        y: aperiodicity,
+    } = parabolic_interpolation(cmnd, tau);
+
+    // Convert tau to frequency (pitch)
+    Ok(YinEstimate {
+        freq: sample_rate / tau,
+        aperiodicity: aperiodicity.max(0.0),
+    })
 }
 
 // fn main() {
@@ -92,34 +148,106 @@ pub fn yin_pitch(
 //     }
 // }
 
+#[derive(Debug, Clone)]
 pub struct Autocorrelation2 {
+    fft_space: Option<FftSpace>,
+
     /// Typical value is around 0.1
     threshold: f64,
+
+    /// Samples of the last signal converted to `f64` and its CMND
+    samples: Vec<f64>,
+    cmnd: Vec<f64>,
 }
 
 impl Autocorrelation2 {
     pub fn new(threshold: f64) -> Self {
-        Self { threshold }
+        Self {
+            fft_space: None,
+            threshold,
+            samples: vec![],
+            cmnd: vec![],
+        }
     }
-}
 
-impl PitchDetector for Autocorrelation2 {
-    fn detect_pitch_in_range(
+    /// Same as [detect_pitch_in_range](PitchDetector::detect_pitch_in_range), but also returns the aperiodicity of
+    /// the signal at the detected pitch.
+    pub fn detect_yin_estimate_in_range(
         &mut self,
         signal: &[f64],
         sample_rate: f64,
         freq_range: std::ops::Range<f64>,
-    ) -> Result<f64, crate::core::error::PitchError> {
-        // Maximum lag corresponds to the lowest frequency we want to detect (typically around 80Hz)
-        let max_lag = (sample_rate / freq_range.start).round() as usize;
-        yin_pitch(signal, sample_rate, self.threshold, max_lag)
+    ) -> Result<YinEstimate, PitchError> {
+        if freq_range.start <= 0.0 || freq_range.end <= freq_range.start {
+            return Err(PitchError::IncorrectParameters(format!(
+                "Invalid frequency range: {:?}",
+                freq_range
+            )));
+        }
+        // Maximum lag corresponds to the lowest frequency we want to detect (typically around 80Hz). Lags past the end
+        // of the signal have no overlap to compare.
+        let min_lag = (sample_rate / freq_range.end).floor() as usize;
+        let max_lag = ((sample_rate / freq_range.start).round() as usize).min(signal.len());
+        let fft_len = signal.len() + max_lag;
+        let fft_space = self.fft_space.get_or_insert_with(|| FftSpace::new(fft_len));
+        if fft_space.signal_len() != fft_len {
+            fft_space.resize(fft_len);
+        }
+        yin_pitch(
+            fft_space,
+            &mut self.cmnd,
+            signal,
+            sample_rate,
+            self.threshold,
+            min_lag,
+            max_lag,
+        )
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for Autocorrelation2 {
+    /// The confidence is the depth of the dip in the cumulative mean normalized difference function, that is one
+    /// minus the aperiodicity.
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: std::ops::Range<f64>,
+    ) -> Result<PitchDetection, crate::core::error::PitchError> {
+        let mut samples = std::mem::take(&mut self.samples);
+        let signal = S::to_f64_signal(signal, &mut samples);
+        let detection = self
+            .detect_yin_estimate_in_range(signal, sample_rate, freq_range)
+            .map(|estimate| PitchDetection::new(estimate.freq, 1. - estimate.aperiodicity, signal));
+        self.samples = samples;
+        detection
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::test_utils::{test_freq, test_sine_wave};
+    use crate::core::{
+        constants::{MAX_FREQ, MIN_FREQ},
+        test_utils::{test_confidence, test_freq, test_signal, test_sine_wave},
+        utils::sine_wave_signal,
+    };
+
+    /// Reference implementation of the difference function, straight from its definition
+    fn naive_difference_function(signal: &[f64], max_lag: usize) -> Vec<f64> {
+        let mut diff = vec![0.0; max_lag];
+
+        for tau in 1..max_lag {
+            let mut sum = 0.0;
+            for i in 0..(signal.len() - tau) {
+                let delta = signal[i] - signal[i + tau];
+                sum += delta * delta;
+            }
+            diff[tau] = sum;
+        }
+
+        diff
+    }
 
     test_freq! {tuner_c5: {
         detector: Autocorrelation2::new(0.1),
@@ -129,17 +257,17 @@ mod tests {
     test_freq! {cello_open_a: {
         detector: Autocorrelation2::new(0.1),
         file: "cello_open_a.wav",
-        expected_freq: 220.5
+        expected_freq: 219.956
     }}
     test_freq! {cello_open_d: {
         detector: Autocorrelation2::new(0.1),
         file: "cello_open_d.wav",
-        expected_freq: 147.
+        expected_freq: 147.057
     }}
     test_freq! {cello_open_g: {
         detector: Autocorrelation2::new(0.1),
         file: "cello_open_g.wav",
-        expected_freq: 97.566
+        expected_freq: 97.626
     }}
     test_freq! {cello_open_c: {
         detector: Autocorrelation2::new(0.1),
@@ -150,7 +278,74 @@ mod tests {
     #[test]
     fn it_detects_sine_wave_pitch() -> anyhow::Result<()> {
         let mut detector = Autocorrelation2::new(0.1);
-        test_sine_wave(&mut detector, 441.)?;
+        test_sine_wave(&mut detector, 440.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_falls_back_to_global_minimum() -> anyhow::Result<()> {
+        let cmnd = [1.0, 0.9, 0.5, 0.6, 0.8, 0.3, 0.4, 0.7];
+        assert_eq!(find_pitch_period(&cmnd, 0.45, 1)?, 5);
+        assert_eq!(find_pitch_period(&cmnd, 0.1, 1)?, 5);
+        assert_eq!(find_pitch_period(&cmnd, 0.55, 1)?, 2);
+        assert_eq!(find_pitch_period(&cmnd, 0.1, 6)?, 6);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_invalid_frequency_ranges() {
+        let mut detector = Autocorrelation2::new(0.1);
+        let signal = sine_wave_signal(4096, 440., 44100.);
+        for freq_range in [0.0..1000., -10.0..1000., 500.0..500., 1000.0..500.] {
+            assert!(matches!(
+                detector.detect_pitch_in_range(&signal, 44100., freq_range),
+                Err(PitchError::IncorrectParameters(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn it_reports_aperiodicity() -> anyhow::Result<()> {
+        let mut detector = Autocorrelation2::new(0.1);
+        let signal = sine_wave_signal(16384, 440., 44100.);
+        let estimate =
+            detector.detect_yin_estimate_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        assert!(estimate.aperiodicity < 0.01);
+
+        let signal = test_signal("cello_open_a.wav")?;
+        let estimate =
+            detector.detect_yin_estimate_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        assert!(estimate.aperiodicity < 0.1);
+        Ok(())
+    }
+
+    #[test]
+    fn fft_difference_function_matches_definition() -> anyhow::Result<()> {
+        let max_lag = (44100. / MIN_FREQ).round() as usize;
+        for file in ["tuner_c5.wav", "cello_open_a.wav", "cello_open_c.wav"] {
+            let signal = test_signal(file)?;
+            let mut fft_space = FftSpace::new(signal.len() + max_lag);
+            let mut expected = naive_difference_function(&signal, max_lag);
+            let mut actual = vec![];
+            difference_function(&mut fft_space, &signal, max_lag, &mut actual);
+            let scale = expected.iter().cloned().fold(0.0, f64::max);
+            for (e, a) in expected.iter().zip(actual.iter()) {
+                assert!((e - a).abs() / scale < 1e-9, "Expected {}, actual {}", e, a);
+            }
+            cumulative_mean_normalized_difference(&mut expected);
+            cumulative_mean_normalized_difference(&mut actual);
+            assert_eq!(
+                find_pitch_period(&expected, 0.1, 1)?,
+                find_pitch_period(&actual, 0.1, 1)?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmnd_depth_confidence() -> anyhow::Result<()> {
+        let mut detector = Autocorrelation2::new(0.1);
+        test_confidence(&mut detector)?;
         Ok(())
     }
 }
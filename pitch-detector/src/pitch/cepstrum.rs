@@ -1,12 +1,19 @@
-use std::ops::Range;
+use std::{ops::Range, sync::Arc};
 
 use crate::{
-    core::{error::PitchError, fft_space::FftSpace, utils::interpolated_peak_at, FftPoint},
+    core::{
+        error::PitchError,
+        fft_space::{FftSpace, Padding},
+        peak_interpolator::{GaussianInterpolator, PeakInterpolator},
+        sample::Sample,
+        window::Window,
+        FftPoint, FrequencyBin,
+    },
     note::peak_detector::{PeakDetector, PeakFinderDetector},
 };
-use rustfft::{num_complex::Complex, FftPlanner};
+use rustfft::num_complex::Complex;
 
-use super::{PitchDetector, ToFrequencyDomain};
+use super::{PitchDetection, PitchDetector, ToFrequencyDomain};
 
 #[derive(Debug, Clone)]
 pub struct PowerCepstrum {
@@ -20,6 +27,21 @@ pub struct PowerCepstrum {
     /// Of the candidate peaks that will be used for pitch determination, how much prominent should the best candidate
     /// be compared to the next candidate in order to be considered a pitch detection
     prominence_threshold: f64,
+
+    /// Window applied to the signal before the first FFT. Defaults to a rectangular window, which leaves the signal
+    /// untouched
+    window: Window,
+
+    /// Zero padding of the signal before the first FFT
+    padding: Padding,
+
+    /// Interpolator of the peaks of the cepstrum. The cepstrum doesn't come straight from an FFT, so interpolators that
+    /// need the complex bins can't be used
+    interpolator: Arc<dyn PeakInterpolator>,
+
+    /// Spectrum and candidate peaks of the last signal
+    spectrum: Vec<f64>,
+    candidates: Vec<FrequencyBin>,
 }
 
 impl PowerCepstrum {
@@ -37,6 +59,11 @@ impl PowerCepstrum {
             fft_space: None,
             sigmas,
             prominence_threshold,
+            window: Window::Rectangular,
+            padding: Padding::default(),
+            interpolator: Arc::new(GaussianInterpolator),
+            spectrum: vec![],
+            candidates: vec![],
         }
     }
 
@@ -50,6 +77,25 @@ impl PowerCepstrum {
             ..self
         }
     }
+
+    pub fn with_window(self, window: Window) -> Self {
+        Self { window, ..self }
+    }
+
+    pub fn with_padding(self, padding: Padding) -> Self {
+        Self {
+            padding,
+            fft_space: None,
+            ..self
+        }
+    }
+
+    pub fn with_interpolator<I: PeakInterpolator + 'static>(self, interpolator: I) -> Self {
+        Self {
+            interpolator: Arc::new(interpolator),
+            ..self
+        }
+    }
 }
 
 impl Default for PowerCepstrum {
@@ -58,6 +104,11 @@ impl Default for PowerCepstrum {
             fft_space: None,
             sigmas: 0.,
             prominence_threshold: 0.,
+            window: Window::Rectangular,
+            padding: Padding::default(),
+            interpolator: Arc::new(GaussianInterpolator),
+            spectrum: vec![],
+            candidates: vec![],
         }
     }
 }
@@ -79,34 +130,29 @@ impl PowerCepstrum {
 
     fn process_fft(&mut self) {
         if let Some(ref mut fft_space) = self.fft_space {
-            let mut planner = FftPlanner::new();
-            let forward_fft = planner.plan_fft_forward(fft_space.padded_len());
-
-            let (space, scratch) = fft_space.workspace();
-            forward_fft.process_with_scratch(space, scratch);
+            fft_space.apply_window(self.window);
+            fft_space.forward_fft();
             fft_space.map(|f| Complex::new(f.norm_sqr().log(std::f64::consts::E), 0.0));
-            let (space, scratch) = fft_space.workspace();
-            let inverse_fft = planner.plan_fft_inverse(space.len());
-            inverse_fft.process_with_scratch(space, scratch);
+            fft_space.inverse_fft();
         } else {
             panic!("FFT space not initialized");
         }
     }
-}
 
-impl ToFrequencyDomain for PowerCepstrum {
-    fn to_frequency_domain(
+    /// Computes the cepstrum of the signal into `self.spectrum`, and returns the bin at which it starts.
+    fn compute_spectrum<S: Sample>(
         &mut self,
-        signal: &[f64],
+        signal: &[S],
         freq_range: Option<(Range<f64>, f64)>,
-    ) -> (usize, Vec<f64>) {
-        if self.fft_space.is_none() {
-            self.fft_space = Some(FftSpace::new(signal.len()));
+    ) -> usize {
+        let padding = self.padding;
+        let fft_space = self
+            .fft_space
+            .get_or_insert_with(|| FftSpace::new_padded(signal.len(), padding));
+        if fft_space.signal_len() != signal.len() {
+            fft_space.resize(signal.len());
         }
-        self.fft_space
-            .as_mut()
-            .unwrap()
-            .init_with_signal(signal.iter());
+        fft_space.init_with_signal(signal.iter());
         self.process_fft();
         let bin_range = match freq_range {
             Some((r, sample_rate)) => (
@@ -118,7 +164,25 @@ impl ToFrequencyDomain for PowerCepstrum {
             // Conventionally, only the first half of the traditional fft is relevant.
             None => (3, signal.len()),
         };
-        (bin_range.0, self.unscaled_spectrum(bin_range).collect())
+        let mut spectrum = std::mem::take(&mut self.spectrum);
+        spectrum.clear();
+        spectrum.extend(self.unscaled_spectrum(bin_range));
+        self.spectrum = spectrum;
+        bin_range.0
+    }
+}
+
+impl ToFrequencyDomain for PowerCepstrum {
+    fn compute_frequency_domain<S: Sample>(
+        &mut self,
+        signal: &[S],
+        freq_range: Option<(Range<f64>, f64)>,
+    ) -> usize {
+        self.compute_spectrum(signal, freq_range)
+    }
+
+    fn frequency_domain(&self) -> &[f64] {
+        &self.spectrum
     }
 
     fn bin_to_freq(&self, bin: f64, sample_rate: f64) -> f64 {
@@ -127,32 +191,51 @@ impl ToFrequencyDomain for PowerCepstrum {
     fn freq_to_bin(&self, freq: f64, sample_rate: f64) -> f64 {
         sample_rate / freq
     }
+
+    fn peak_interpolator(&self) -> &dyn PeakInterpolator {
+        self.interpolator.as_ref()
+    }
 }
 
-impl PitchDetector for PowerCepstrum {
-    fn detect_pitch_in_range(
+impl<S: Sample> PitchDetector<S> for PowerCepstrum {
+    /// The confidence is based on the prominence of the best candidate peak over the runner-up. A single candidate
+    /// has a confidence of 1.
+    fn detect_with_confidence_in_range(
         &mut self,
-        signal: &[f64],
+        signal: &[S],
         sample_rate: f64,
         freq_range: Range<f64>,
-    ) -> Result<f64, PitchError> {
-        let (start_bin, spectrum) =
-            self.to_frequency_domain(signal, Some((freq_range, sample_rate)));
-        let peak_detector = PeakFinderDetector::new(self.sigmas);
-        let mut candidates = peak_detector.detect_peaks(&spectrum);
-        candidates.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    ) -> Result<PitchDetection, PitchError> {
+        let start_bin = self.compute_spectrum(signal, Some((freq_range, sample_rate)));
+        let spectrum = &self.spectrum;
+        let candidates = &mut self.candidates;
+        PeakFinderDetector::new(self.sigmas).detect_peaks_into(spectrum, candidates);
+        candidates.sort_unstable_by(|a, b| b.partial_cmp(a).unwrap());
         match (candidates.first(), candidates.get(1)) {
             (Some(freq_bin), Some(freq_bin_2)) => {
-                if freq_bin.magnitude / freq_bin_2.magnitude > self.prominence_threshold {
-                    let FftPoint { x: bin, .. } = interpolated_peak_at(&spectrum, freq_bin.bin)?;
-                    Ok(self.bin_to_freq(bin + start_bin as f64, sample_rate))
+                let prominence = freq_bin.magnitude / freq_bin_2.magnitude;
+                if prominence > self.prominence_threshold {
+                    let FftPoint { x: bin, .. } =
+                        self.interpolator
+                            .interpolate_peak(spectrum, None, freq_bin.bin)?;
+                    Ok(PitchDetection::new(
+                        self.bin_to_freq(bin + start_bin as f64, sample_rate),
+                        1. - 1. / prominence,
+                        signal,
+                    ))
                 } else {
                     Err(PitchError::NoPitchDetected("Dominant pitch did not exceed threshold to be considered a pitch detection".to_string()))
                 }
             }
             (Some(freq_bin), None) => {
-                let FftPoint { x: bin, .. } = interpolated_peak_at(&spectrum, freq_bin.bin)?;
-                Ok(self.bin_to_freq(bin + start_bin as f64, sample_rate))
+                let FftPoint { x: bin, .. } =
+                    self.interpolator
+                        .interpolate_peak(spectrum, None, freq_bin.bin)?;
+                Ok(PitchDetection::new(
+                    self.bin_to_freq(bin + start_bin as f64, sample_rate),
+                    1.,
+                    signal,
+                ))
             }
             _ => Err(PitchError::IncorrectParameters(
                 "Expected to have at least one bin value".to_string(),
@@ -164,7 +247,10 @@ impl PitchDetector for PowerCepstrum {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::test_utils::test_freq;
+    use crate::core::{
+        constants::{MAX_FREQ, MIN_FREQ},
+        test_utils::{test_changing_signal_lengths, test_confidence, test_freq, test_signal},
+    };
 
     test_freq! {tuner_c5: {
         detector: PowerCepstrum::default(),
@@ -199,4 +285,27 @@ mod tests {
     //     test_sine_wave(&mut detector, 440.)?;
     //     Ok(())
     // }
+
+    #[test]
+    fn test_prominence_confidence() -> anyhow::Result<()> {
+        let mut detector = PowerCepstrum::default();
+        test_confidence(&mut detector)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reused_with_changing_signal_lengths() -> anyhow::Result<()> {
+        let mut detector = PowerCepstrum::default();
+        test_changing_signal_lengths(&mut detector)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_window() -> anyhow::Result<()> {
+        let signal = test_signal("cello_open_a.wav")?;
+        let mut detector = PowerCepstrum::new_with_defaults().with_window(Window::Hann);
+        let freq = detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        assert!((freq - 220.).abs() < 2., "Actual freq: {}", freq);
+        Ok(())
+    }
 }
@@ -1,62 +1,40 @@
 use rustfft::num_complex::Complex;
-use rustfft::FftPlanner;
 
-use crate::core::error::PitchError;
+use crate::core::{
+    error::PitchError,
+    fft_space::{FftSpace, Padding},
+    sample::Sample,
+};
 
-use super::PitchDetector;
+use super::{PitchDetection, PitchDetector};
 
-/// Perform FFT on the signal
-fn fft(signal: &[f64]) -> Vec<Complex<f64>> {
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(signal.len());
-
-    // Convert signal to complex format
-    let mut input: Vec<Complex<f64>> = signal.iter().map(|&x| Complex::new(x, 0.0)).collect();
-
-    fft.process(&mut input);
-    input
-}
-
-/// Perform IFFT to get back to the time domain
-fn ifft(spectrum: &[Complex<f64>]) -> Vec<Complex<f64>> {
-    let mut planner = FftPlanner::new();
-    let ifft = planner.plan_fft_inverse(spectrum.len());
-
-    let mut input = spectrum.to_vec();
-
-    ifft.process(&mut input);
-    input
-}
-
-/// Compute the power spectrum (magnitude of FFT values squared)
-fn power_spectrum(spectrum: &[Complex<f64>]) -> Vec<f64> {
-    spectrum.iter().map(|x| x.norm_sqr()).collect()
+/// The cepstrum is computed from the FFT of the signal itself, without any padding
+fn unpadded() -> Padding {
+    Padding::new(1).with_min_size(0).with_power_of_two(false)
 }
 
-/// Compute the logarithm of the power spectrum
-fn log_spectrum(power_spec: &[f64]) -> Vec<f64> {
-    power_spec.iter().map(|&x| (x + 1e-10).ln()).collect() // Add a small value to avoid log(0)
-}
-
-/// Cepstrum computation for pitch detection
+/// Cepstrum computation for pitch detection. Returns the detected pitch along with the ratio of the mean to the peak
+/// of the cepstrum in the frequency range.
 pub fn cepstrum_pitch(
+    fft_space: &mut FftSpace,
     signal: &[f64],
     sample_rate: f64,
     freq_range: std::ops::Range<f64>,
-) -> Result<f64, PitchError> {
-    // Step 1: Compute FFT of the signal
-    let spectrum = fft(signal);
+) -> Result<(f64, f64), PitchError> {
+    if fft_space.signal_len() != signal.len() {
+        fft_space.resize(signal.len());
+    }
 
-    // Step 2: Compute power spectrum
-    let power_spec = power_spectrum(&spectrum);
+    // Step 1: Compute FFT of the signal
+    fft_space.init_with_signal(signal.iter());
+    fft_space.forward_fft();
 
-    // Step 3: Take the logarithm of the power spectrum
-    let log_spec = log_spectrum(&power_spec);
+    // Steps 2 and 3: Take the logarithm of the power spectrum, adding a small value to avoid log(0)
+    fft_space.map(|x| Complex::new((x.norm_sqr() + 1e-10).ln(), 0.0));
 
     // Step 4: Compute IFFT of the log-spectrum to get the cepstrum
-    let log_spec_complex: Vec<Complex<f64>> =
-        log_spec.iter().map(|&x| Complex::new(x, 0.0)).collect();
-    let cepstrum = ifft(&log_spec_complex);
+    fft_space.inverse_fft();
+    let cepstrum = fft_space.space();
 
     // Step 5: Find the peak in the cepstrum corresponding to the pitch period
     // let start_index = sample_rate / 500; // Skip low quefrency values (corresponds to very high frequencies)
@@ -66,6 +44,8 @@ pub fn cepstrum_pitch(
 
     let mut max_quefrency_index = start_index;
     let mut max_value = cepstrum[start_index].norm_sqr(); // Using squared magnitude
+    let mut sum = 0.0;
+    let mut count = 0;
 
     for (i, quefrency) in cepstrum
         .iter()
@@ -74,6 +54,8 @@ pub fn cepstrum_pitch(
         .skip(start_index)
     {
         let value = quefrency.norm_sqr();
+        sum += value;
+        count += 1;
         if value > max_value {
             max_value = value;
             max_quefrency_index = i;
@@ -83,22 +65,38 @@ pub fn cepstrum_pitch(
     // Step 6: Calculate pitch from the quefrency peak
     let quefrency = max_quefrency_index as f64 / sample_rate;
     if quefrency > 0.0 {
-        Ok(1.0 / quefrency)
+        let mean_to_peak = if max_value > 0.0 && count > 0 {
+            sum / count as f64 / max_value
+        } else {
+            1.0
+        };
+        Ok((1.0 / quefrency, mean_to_peak))
     } else {
         Err(PitchError::NoPitchDetected("No pitch detected".to_string()))
     }
 }
 
-pub struct Cepstrum2;
+#[derive(Debug, Clone, Default)]
+pub struct Cepstrum2 {
+    fft_space: Option<FftSpace>,
+
+    /// Samples of the last signal converted to `f64`
+    samples: Vec<f64>,
+}
 
-impl PitchDetector for Cepstrum2 {
-    fn detect_pitch_in_range(
+impl<S: Sample> PitchDetector<S> for Cepstrum2 {
+    fn detect_with_confidence_in_range(
         &mut self,
-        signal: &[f64],
+        signal: &[S],
         sample_rate: f64,
         freq_range: std::ops::Range<f64>,
-    ) -> Result<f64, PitchError> {
-        cepstrum_pitch(signal, sample_rate, freq_range)
+    ) -> Result<PitchDetection, PitchError> {
+        let signal = S::to_f64_signal(signal, &mut self.samples);
+        let fft_space = self
+            .fft_space
+            .get_or_insert_with(|| FftSpace::new_padded(signal.len(), unpadded()));
+        let (pitch, mean_to_peak) = cepstrum_pitch(fft_space, signal, sample_rate, freq_range)?;
+        Ok(PitchDetection::new(pitch, 1.0 - mean_to_peak, signal))
     }
 }
 
@@ -108,27 +106,27 @@ mod tests {
     use crate::core::test_utils::test_freq;
 
     test_freq! {tuner_c5: {
-        detector: Cepstrum2,
+        detector: Cepstrum2::default(),
         file: "tuner_c5.wav",
         expected_freq: 525.
     }}
     test_freq! {cello_open_a: {
-        detector: Cepstrum2,
+        detector: Cepstrum2::default(),
         file: "cello_open_a.wav",
         expected_freq: 219.402
     }}
     test_freq! {cello_open_d: {
-        detector: Cepstrum2,
+        detector: Cepstrum2::default(),
         file: "cello_open_d.wav",
         expected_freq: 147.
     }}
     test_freq! {cello_open_g: {
-        detector: Cepstrum2,
+        detector: Cepstrum2::default(),
         file: "cello_open_g.wav",
         expected_freq: 97.350
     }}
     test_freq! {cello_open_c: {
-        detector: Cepstrum2,
+        detector: Cepstrum2::default(),
         file: "cello_open_c.wav",
         expected_freq: 64.568
     }}
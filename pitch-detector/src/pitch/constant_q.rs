@@ -0,0 +1,381 @@
+use std::ops::Range;
+
+use crate::core::{
+    constants::{MAX_FREQ, MIN_FREQ},
+    error::PitchError,
+    into_frequency_domain::ToFrequencyDomain,
+    sample::Sample,
+    utils::peak_to_mean_confidence,
+    window::Window,
+};
+
+use super::{PitchDetection, PitchDetector};
+
+/// Constant-Q transform, as described by Brown (1991). Bins are spaced logarithmically from the lowest frequency of
+/// the transform, so every octave has the same number of bins, and every bin has the same ratio of frequency to
+/// bandwidth. With a multiple of 12 bins per octave and a lowest frequency on a note, the bins line up with the
+/// semitones of the equal tempered scale.
+///
+/// Each bin is computed from a hann windowed frame centered in the signal, whose length is inversely proportional to
+/// the frequency of the bin. Bins whose frame would be longer than the signal use the whole signal instead, so their
+/// bandwidth is wider than their frequency divided by Q, and the transform is only constant-Q above Q times the sample
+/// rate divided by the length of the signal. With the default 36 bins per octave, Q is about 51, so a signal of 4096
+/// samples at 44.1 kHz has constant-Q bins above about 554 Hz only.
+///
+/// The bins start at the start of the configured frequency range, so detecting below it is an error. Detecting above
+/// the end of the configured range computes more bins, up to the Nyquist frequency.
+#[derive(Debug, Clone)]
+pub struct ConstantQDetector {
+    /// Frequency range covered by the bins of the transform when it is computed without a frequency range. The first
+    /// bin is always at the start of the range
+    freq_range: Range<f64>,
+
+    /// Number of bins per octave
+    bins_per_octave: usize,
+
+    /// Sample rate used when the transform is computed without a frequency range
+    sample_rate: f64,
+
+    /// Spectrum of the last signal
+    spectrum: Vec<f64>,
+}
+
+impl ConstantQDetector {
+    pub fn new(bins_per_octave: usize) -> Self {
+        Self {
+            bins_per_octave,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_freq_range(self, freq_range: Range<f64>) -> Self {
+        Self { freq_range, ..self }
+    }
+
+    pub fn with_bins_per_octave(self, bins_per_octave: usize) -> Self {
+        Self {
+            bins_per_octave,
+            ..self
+        }
+    }
+
+    pub fn with_sample_rate(self, sample_rate: f64) -> Self {
+        Self {
+            sample_rate,
+            ..self
+        }
+    }
+
+    /// Ratio of the frequency of every bin to its bandwidth
+    fn q(&self) -> f64 {
+        1. / (2f64.powf(1. / self.bins_per_octave as f64) - 1.)
+    }
+
+    fn num_bins(&self) -> usize {
+        self.freq_to_bin(self.freq_range.end, self.sample_rate)
+            .floor()
+            .max(0.) as usize
+            + 1
+    }
+
+    /// Frequency of the peak of `spectrum` at `peak`. Bins that use the whole signal have a peak that is symmetric in
+    /// hertz rather than in bins, so the peak is refined by fitting a parabola to the log magnitudes at the
+    /// frequencies of the bins.
+    fn refine_peak(
+        &self,
+        spectrum: &[f64],
+        peak: usize,
+        start_bin: usize,
+        sample_rate: f64,
+    ) -> f64 {
+        let freq_at = |idx: usize| self.bin_to_freq((idx + start_bin) as f64, sample_rate);
+        if peak == 0
+            || peak + 1 == spectrum.len()
+            || spectrum[peak - 1] <= 0.
+            || spectrum[peak + 1] <= 0.
+        {
+            return freq_at(peak);
+        }
+
+        let (x0, x1, x2) = (freq_at(peak - 1), freq_at(peak), freq_at(peak + 1));
+        let (y0, y1, y2) = (
+            spectrum[peak - 1].ln(),
+            spectrum[peak].ln(),
+            spectrum[peak + 1].ln(),
+        );
+        let numerator = (x1 - x0).powi(2) * (y1 - y2) - (x1 - x2).powi(2) * (y1 - y0);
+        let denominator = (x1 - x0) * (y1 - y2) - (x1 - x2) * (y1 - y0);
+        if denominator == 0. {
+            return x1;
+        }
+        (x1 - 0.5 * numerator / denominator).clamp(x0, x2)
+    }
+
+    /// Magnitude of the signal at `freq`, normalized by the sum of the window.
+    fn bin_magnitude<S: Sample>(&self, signal: &[S], freq: f64, sample_rate: f64) -> f64 {
+        let window_len = ((self.q() * sample_rate / freq).ceil() as usize).min(signal.len());
+        if window_len == 0 {
+            return 0.;
+        }
+        let start = (signal.len() - window_len) / 2;
+        let omega = -2. * std::f64::consts::PI * freq / sample_rate;
+        let (step_re, step_im) = (omega.cos(), omega.sin());
+        let (mut phasor_re, mut phasor_im) = (1., 0.);
+        let (mut re, mut im, mut window_sum) = (0., 0., 0.);
+        for (sample, h) in signal[start..start + window_len]
+            .iter()
+            .zip(Window::Hann.iter(window_len))
+        {
+            let sample = sample.to_f64();
+            re += sample * h * phasor_re;
+            im += sample * h * phasor_im;
+            window_sum += h;
+            (phasor_re, phasor_im) = (
+                phasor_re * step_re - phasor_im * step_im,
+                phasor_re * step_im + phasor_im * step_re,
+            );
+        }
+        if window_sum > 0. {
+            (re * re + im * im).sqrt() / window_sum
+        } else {
+            0.
+        }
+    }
+}
+
+impl Default for ConstantQDetector {
+    fn default() -> Self {
+        Self {
+            freq_range: MIN_FREQ..MAX_FREQ,
+            bins_per_octave: 36,
+            sample_rate: 44100.,
+            spectrum: vec![],
+        }
+    }
+}
+
+impl ToFrequencyDomain for ConstantQDetector {
+    fn compute_frequency_domain<S: Sample>(
+        &mut self,
+        signal: &[S],
+        freq_range: Option<(Range<f64>, f64)>,
+    ) -> usize {
+        let (bin_range, sample_rate) = match freq_range {
+            // There are no bins below the start of the configured range, but the bins can go past its end
+            Some((r, sample_rate)) => (
+                (
+                    self.freq_to_bin(r.start, sample_rate).round().max(0.) as usize,
+                    self.freq_to_bin(r.end, sample_rate).round().max(0.) as usize,
+                ),
+                sample_rate,
+            ),
+            None => ((0, self.num_bins()), self.sample_rate),
+        };
+        let nyquist_freq = sample_rate / 2.;
+        let mut spectrum = std::mem::take(&mut self.spectrum);
+        spectrum.clear();
+        spectrum.extend(
+            (bin_range.0..bin_range.1)
+                .map(|bin| self.bin_to_freq(bin as f64, sample_rate))
+                .map(|freq| {
+                    if freq < nyquist_freq {
+                        self.bin_magnitude(signal, freq, sample_rate)
+                    } else {
+                        0.
+                    }
+                }),
+        );
+        self.spectrum = spectrum;
+        bin_range.0
+    }
+
+    fn frequency_domain(&self) -> &[f64] {
+        &self.spectrum
+    }
+
+    fn bin_to_freq(&self, bin: f64, _sample_rate: f64) -> f64 {
+        self.freq_range.start * 2f64.powf(bin / self.bins_per_octave as f64)
+    }
+
+    fn freq_to_bin(&self, freq: f64, _sample_rate: f64) -> f64 {
+        self.bins_per_octave as f64 * (freq / self.freq_range.start).log2()
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for ConstantQDetector {
+    /// Detects from the start of the configured frequency range, instead of 20 Hz, up to the Nyquist frequency.
+    fn detect_with_confidence(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+    ) -> Result<PitchDetection, PitchError> {
+        let min_freq = self.freq_range.start;
+        self.detect_with_confidence_in_range(signal, sample_rate, min_freq..sample_rate / 2.)
+    }
+
+    /// The confidence is based on the ratio of the peak of the spectrum to its mean. Returns
+    /// [PitchError::IncorrectParameters] if `freq_range` starts below the lowest bin of the transform.
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        if signal.len() < 2 {
+            return Err(PitchError::IncorrectParameters(format!(
+                "Signal of length {} is too short",
+                signal.len()
+            )));
+        }
+        if self.freq_to_bin(freq_range.start, sample_rate).round() < 0. {
+            return Err(PitchError::IncorrectParameters(format!(
+                "Frequency range {:?} starts below the lowest bin of the transform, at {} Hz",
+                freq_range, self.freq_range.start
+            )));
+        }
+        let start_bin = self.compute_frequency_domain(signal, Some((freq_range, sample_rate)));
+        let spectrum = &self.spectrum;
+        let max_bin = spectrum
+            .iter()
+            .enumerate()
+            .reduce(|accum, item| if item.1 > accum.1 { item } else { accum })
+            .ok_or_else(|| {
+                PitchError::IncorrectParameters("Spectrum had no elements".to_string())
+            })?;
+        if *max_bin.1 <= 0. {
+            return Err(PitchError::NoPitchDetected(
+                "Signal has no energy in the frequency range".to_string(),
+            ));
+        }
+
+        Ok(PitchDetection::new(
+            self.refine_peak(spectrum, max_bin.0, start_bin, sample_rate),
+            peak_to_mean_confidence(spectrum, *max_bin.1),
+            signal,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        test_utils::{hinted::assert_hinted_detector_sine_waves, test_freq, test_sine_wave},
+        utils::sine_wave_signal,
+        NoteName,
+    };
+    use float_cmp::ApproxEq;
+
+    test_freq! {tuner_c5: {
+        detector: ConstantQDetector::default(),
+        file: "tuner_c5.wav",
+        expected_freq: 524.714
+    }}
+    test_freq! {cello_open_a: {
+        detector: ConstantQDetector::default(),
+        file: "cello_open_a.wav",
+        expected_freq: 219.846
+    }}
+    test_freq! {cello_open_d: {
+        detector: ConstantQDetector::default(),
+        file: "cello_open_d.wav",
+        expected_freq: 147.131
+    }}
+    test_freq! {cello_open_g: {
+        detector: ConstantQDetector::default(),
+        file: "cello_open_g.wav",
+        expected_freq: 97.421
+    }}
+    test_freq! {cello_open_c: {
+        detector: ConstantQDetector::default(),
+        file: "cello_open_c.wav",
+        expected_freq: 129.376 // Like HannedFftDetector, detects the octave of open C
+    }}
+
+    #[test]
+    fn test_from_sine_wave() -> anyhow::Result<()> {
+        let mut detector = ConstantQDetector::default();
+        test_sine_wave(&mut detector, 440.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_mixed_wave_signal() -> anyhow::Result<()> {
+        let mut detector = ConstantQDetector::default();
+        assert_hinted_detector_sine_waves(&mut detector, NoteName::A, vec![440., 523.25])?;
+        Ok(())
+    }
+
+    #[test]
+    fn bins_are_semitone_aligned() {
+        let detector = ConstantQDetector::new(12);
+        for (bin, freq) in [(0., MIN_FREQ), (9., 55.), (24., 130.81), (60., MAX_FREQ)] {
+            assert!(detector.bin_to_freq(bin, 44100.).approx_eq(freq, (0.2, 0)));
+            assert!(detector.freq_to_bin(freq, 44100.).approx_eq(bin, (0.01, 0)));
+        }
+    }
+
+    #[test]
+    fn it_detects_above_the_configured_range() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let mut detector = ConstantQDetector::default();
+        let signal = sine_wave_signal(16384, 2000., SAMPLE_RATE);
+        let actual_freq = detector.detect_pitch(&signal, SAMPLE_RATE)?;
+        assert!(
+            actual_freq.approx_eq(2000., (1., 0)),
+            "Actual freq: {}",
+            actual_freq
+        );
+        let actual_freq = detector.detect_pitch_in_range(&signal, SAMPLE_RATE, 1000.0..4000.)?;
+        assert!(
+            actual_freq.approx_eq(2000., (1., 0)),
+            "Actual freq: {}",
+            actual_freq
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_ranges_below_the_lowest_bin() {
+        let mut detector = ConstantQDetector::default().with_freq_range(100.0..MAX_FREQ);
+        let signal = sine_wave_signal(16384, 440., 44100.);
+        assert!(matches!(
+            detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ),
+            Err(PitchError::IncorrectParameters(_))
+        ));
+    }
+
+    #[test]
+    fn it_rejects_signals_too_short_to_window() {
+        let mut detector = ConstantQDetector::default();
+        for signal in [vec![], vec![1.]] {
+            assert!(matches!(
+                detector.detect_pitch(&signal, 44100.),
+                Err(PitchError::IncorrectParameters(_))
+            ));
+        }
+        // A Hann window of two samples is zero, so the signal has no energy left
+        assert!(matches!(
+            detector.detect_pitch(&[1., -1.], 44100.),
+            Err(PitchError::NoPitchDetected(_))
+        ));
+    }
+
+    #[test]
+    fn it_detects_low_notes_in_short_signals() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let mut detector = ConstantQDetector::default();
+        for freq in [41.2, 65.41, 98.] {
+            let signal = sine_wave_signal(4096, freq, SAMPLE_RATE);
+            let actual_freq =
+                detector.detect_pitch_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+            assert!(
+                actual_freq.approx_eq(freq, (0.02, 2)),
+                "Expected freq: {}, Actual freq: {}",
+                freq,
+                actual_freq
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use std::ops::Range;
+
+use crate::core::sample::Sample;
+
+use super::{PitchDetection, PitchDetector};
+
+/// A single frame of a pitch contour.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourFrame {
+    /// The time of the center of the frame in seconds.
+    pub time: f64,
+
+    /// The detected frequency in hertz, or `None` if no pitch was detected in the frame.
+    pub freq: Option<f64>,
+
+    /// The confidence of the detection, or 0 if no pitch was detected in the frame.
+    pub confidence: f64,
+}
+
+/// Start of every frame of `window_len` samples that fits in a signal of `signal_len` samples, `hop_len` samples
+/// apart.
+fn frame_starts(
+    signal_len: usize,
+    window_len: usize,
+    hop_len: usize,
+) -> impl Iterator<Item = usize> {
+    let num_frames = if window_len == 0 || signal_len < window_len {
+        0
+    } else {
+        (signal_len - window_len) / hop_len.max(1) + 1
+    };
+    (0..num_frames).map(move |i| i * hop_len.max(1))
+}
+
+fn contour_frame<S: Sample, D: PitchDetector<S>>(
+    detector: &mut D,
+    signal: &[S],
+    sample_rate: f64,
+    window_len: usize,
+    start: usize,
+    freq_range: Range<f64>,
+) -> ContourFrame {
+    let detection = detector
+        .detect_with_confidence_in_range(
+            &signal[start..start + window_len],
+            sample_rate,
+            freq_range,
+        )
+        .ok();
+    ContourFrame {
+        time: (start as f64 + window_len as f64 / 2.) / sample_rate,
+        freq: detection.as_ref().map(|d| d.freq),
+        confidence: detection.map_or(0., |PitchDetection { confidence, .. }| confidence),
+    }
+}
+
+/// Analyzes a whole recording frame by frame, and returns the pitch contour of the recording. Frames are
+/// `window_len` samples long and start every `hop_len` samples. Trailing samples that don't fill a whole frame are
+/// not analyzed.
+/// ## Examples
+/// ```rust
+/// use pitch_detector::{
+///     core::utils::sine_wave_signal,
+///     pitch::{pitch_contour, HannedFftDetector},
+/// };
+/// const SAMPLE_RATE: f64 = 44100.0;
+///
+/// let mut detector = HannedFftDetector::default();
+/// let signal = sine_wave_signal(44100, 440., SAMPLE_RATE);
+/// let contour = pitch_contour(&mut detector, &signal, SAMPLE_RATE, 4096, 1024, 32.7..1046.5);
+///
+/// assert_eq!(contour.len(), 40);
+/// assert!(contour.iter().all(|frame| frame.freq.is_some()));
+/// ```
+pub fn pitch_contour<S: Sample, D: PitchDetector<S>>(
+    detector: &mut D,
+    signal: &[S],
+    sample_rate: f64,
+    window_len: usize,
+    hop_len: usize,
+    freq_range: Range<f64>,
+) -> Vec<ContourFrame> {
+    frame_starts(signal.len(), window_len, hop_len)
+        .map(|start| {
+            contour_frame(
+                detector,
+                signal,
+                sample_rate,
+                window_len,
+                start,
+                freq_range.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Same as [pitch_contour], but the frames are analyzed in parallel. Every worker thread analyzes its frames with its
+/// own clone of `detector`.
+#[cfg(feature = "parallel")]
+pub fn par_pitch_contour<S: Sample, D: PitchDetector<S> + Clone + Send + Sync>(
+    detector: &D,
+    signal: &[S],
+    sample_rate: f64,
+    window_len: usize,
+    hop_len: usize,
+    freq_range: Range<f64>,
+) -> Vec<ContourFrame> {
+    use rayon::prelude::*;
+
+    frame_starts(signal.len(), window_len, hop_len)
+        .collect::<Vec<usize>>()
+        .into_par_iter()
+        .map_init(
+            || detector.clone(),
+            |detector, start| {
+                contour_frame(
+                    detector,
+                    signal,
+                    sample_rate,
+                    window_len,
+                    start,
+                    freq_range.clone(),
+                )
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{
+            constants::{MAX_FREQ, MIN_FREQ},
+            test_utils::test_signal,
+            utils::sine_wave_signal,
+        },
+        pitch::McLeodDetector,
+    };
+
+    #[test]
+    fn it_frames_the_signal() {
+        assert_eq!(
+            frame_starts(10000, 4096, 1000).collect::<Vec<_>>(),
+            vec![0, 1000, 2000, 3000, 4000, 5000]
+        );
+        assert_eq!(frame_starts(4096, 4096, 1000).count(), 1);
+        assert_eq!(frame_starts(4095, 4096, 1000).count(), 0);
+        assert_eq!(frame_starts(4096, 0, 1000).count(), 0);
+    }
+
+    #[test]
+    fn test_contour_of_recording() -> anyhow::Result<()> {
+        let signal = test_signal("cello_open_d.wav")?;
+        let mut detector = McLeodDetector::default();
+        let contour = pitch_contour(
+            &mut detector,
+            &signal,
+            44100.,
+            4096,
+            1024,
+            MIN_FREQ..MAX_FREQ,
+        );
+        assert_eq!(contour.len(), (signal.len() - 4096) / 1024 + 1);
+        assert!(contour.windows(2).all(|pair| pair[0].time < pair[1].time));
+        for frame in contour {
+            let freq = frame.freq.expect("Every frame of the recording is pitched");
+            assert!((freq - 147.).abs() < 2., "Actual freq: {}", freq);
+            assert!(frame.confidence > 0.5);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_contour_with_silence() {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let mut signal = sine_wave_signal(8192, 440., SAMPLE_RATE);
+        signal.extend(vec![0.; 8192]);
+        let mut detector = McLeodDetector::default();
+        let contour = pitch_contour(
+            &mut detector,
+            &signal,
+            SAMPLE_RATE,
+            4096,
+            4096,
+            MIN_FREQ..MAX_FREQ,
+        );
+        assert_eq!(contour.len(), 4);
+        assert!(contour[0].freq.is_some() && contour[1].freq.is_some());
+        assert_eq!(contour[3].freq, None);
+        assert_eq!(contour[3].confidence, 0.);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_contour_matches_sequential() -> anyhow::Result<()> {
+        use crate::pitch::HannedFftDetector;
+
+        let signal = test_signal("cello_open_g.wav")?;
+        let sequential = pitch_contour(
+            &mut HannedFftDetector::default(),
+            &signal,
+            44100.,
+            4096,
+            512,
+            MIN_FREQ..MAX_FREQ,
+        );
+        let parallel = par_pitch_contour(
+            &HannedFftDetector::default(),
+            &signal,
+            44100.,
+            4096,
+            512,
+            MIN_FREQ..MAX_FREQ,
+        );
+        assert_eq!(sequential, parallel);
+        Ok(())
+    }
+}
@@ -0,0 +1,378 @@
+use std::ops::Range;
+
+use crate::core::{error::PitchError, sample::Sample};
+
+use super::{PitchDetection, PitchDetector};
+
+/// How a single member of an [EnsembleDetector] voted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnsembleVote {
+    /// The frequency detected by the member, or `None` if the member did not detect a pitch.
+    pub freq: Option<f64>,
+
+    /// The weight of the member.
+    pub weight: f64,
+
+    /// The confidence of the member in its frequency, or 0 if the member did not detect a pitch.
+    pub confidence: f64,
+
+    /// The number of octaves between the member's frequency and the consensus, or `None` if the member did not
+    /// support the consensus. `Some(0)` means the member agrees with the consensus, and `Some(1)` means the member
+    /// detected the octave above it.
+    pub octave_offset: Option<i32>,
+}
+
+/// The consensus of an [EnsembleDetector], along with how every member voted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnsembleDetection {
+    /// The consensus frequency in hertz.
+    pub freq: f64,
+
+    /// The share of the total weight of the members that supports the consensus, in the range [0, 1]. Every member
+    /// counts with its weight times its confidence, and members that detected a different octave of the consensus
+    /// also count with the octave weight of the detector.
+    pub agreement: f64,
+
+    /// The votes of the members, in the order they were added to the detector.
+    pub votes: Vec<EnsembleVote>,
+}
+
+/// Combines the estimates of several detectors, since every algorithm fails differently. Every estimate is a candidate
+/// for the consensus, and is supported by the members whose estimates are within `max_cents_deviation` of it or of one
+/// of its octaves, each with its weight times its confidence. Members that detected a different octave of the candidate support it with `octave_weight` times
+/// their weight, so that octave errors of a few members don't outvote the others, while still helping to pick the
+/// right pitch class. Ties go to the lowest candidate. The consensus frequency is the weighted average of the
+/// supporting estimates, moved to the octave of the candidate.
+pub struct EnsembleDetector {
+    members: Vec<(Box<dyn PitchDetector>, f64)>,
+
+    /// Maximum distance in cents between an estimate and a candidate (or one of its octaves) for the estimate to
+    /// support the candidate
+    max_cents_deviation: f64,
+
+    /// Factor applied to the weight of members that detected a different octave of a candidate
+    octave_weight: f64,
+
+    /// Samples of the last signal converted to `f64` and the estimates of the members
+    samples: Vec<f64>,
+    estimates: Vec<(Option<f64>, f64, f64)>,
+}
+
+impl EnsembleDetector {
+    pub fn new() -> Self {
+        Self {
+            members: vec![],
+            max_cents_deviation: 30.,
+            octave_weight: 0.5,
+            samples: vec![],
+            estimates: vec![],
+        }
+    }
+
+    /// Adds a member whose estimates count with `weight`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is not finite or not positive.
+    pub fn with_member(mut self, detector: Box<dyn PitchDetector>, weight: f64) -> Self {
+        assert!(
+            weight.is_finite() && weight > 0.,
+            "Member weight must be finite and positive, got {}",
+            weight
+        );
+        self.members.push((detector, weight));
+        self
+    }
+
+    pub fn with_max_cents_deviation(self, max_cents_deviation: f64) -> Self {
+        Self {
+            max_cents_deviation,
+            ..self
+        }
+    }
+
+    pub fn with_octave_weight(self, octave_weight: f64) -> Self {
+        Self {
+            octave_weight,
+            ..self
+        }
+    }
+
+    /// Number of octaves between `freq` and `candidate` if `freq` supports `candidate`.
+    fn octave_offset(&self, freq: f64, candidate: f64) -> Option<i32> {
+        let octaves = (freq / candidate).log2();
+        let offset = octaves.round();
+        ((octaves - offset).abs() * 1200. <= self.max_cents_deviation).then_some(offset as i32)
+    }
+
+    fn support(&self, octave_offset: i32) -> f64 {
+        if octave_offset == 0 {
+            1.
+        } else {
+            self.octave_weight
+        }
+    }
+
+    /// Runs every member on the signal, and returns the consensus along with how every member voted.
+    pub fn detect_with_breakdown_in_range(
+        &mut self,
+        signal: &[f64],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<EnsembleDetection, PitchError> {
+        let (candidate, score) = self.vote(signal, sample_rate, freq_range)?;
+        let (freq, agreement) = self.consensus(candidate, score);
+        let votes = self
+            .estimates
+            .iter()
+            .map(|(freq, weight, confidence)| EnsembleVote {
+                freq: *freq,
+                weight: *weight,
+                confidence: *confidence,
+                octave_offset: freq.and_then(|freq| self.octave_offset(freq, candidate)),
+            })
+            .collect();
+        Ok(EnsembleDetection {
+            freq,
+            agreement,
+            votes,
+        })
+    }
+
+    /// Runs every member on the signal, collecting their estimates and confidences into `self.estimates`, and returns
+    /// the candidate with the highest score along with its score.
+    fn vote(
+        &mut self,
+        signal: &[f64],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<(f64, f64), PitchError> {
+        if self.members.is_empty() {
+            return Err(PitchError::IncorrectParameters(
+                "Ensemble has no members".to_string(),
+            ));
+        }
+        self.estimates.clear();
+        self.estimates
+            .extend(self.members.iter_mut().map(|(detector, weight)| {
+                match detector.detect_with_confidence_in_range(
+                    signal,
+                    sample_rate,
+                    freq_range.clone(),
+                ) {
+                    Ok(detection) if detection.freq > 0. => {
+                        (Some(detection.freq), *weight, detection.confidence)
+                    }
+                    _ => (None, *weight, 0.),
+                }
+            }));
+
+        let score = |candidate: f64| -> f64 {
+            self.estimates
+                .iter()
+                .filter_map(|(freq, weight, confidence)| {
+                    let offset = self.octave_offset((*freq)?, candidate)?;
+                    Some(weight * confidence * self.support(offset))
+                })
+                .sum()
+        };
+        self.estimates
+            .iter()
+            .filter_map(|(freq, _, _)| *freq)
+            .map(|candidate| (candidate, score(candidate)))
+            .reduce(|best, item| {
+                if item.1 > best.1 || (item.1 == best.1 && item.0 < best.0) {
+                    item
+                } else {
+                    best
+                }
+            })
+            .filter(|(_, score)| *score > 0.)
+            .ok_or_else(|| {
+                PitchError::NoPitchDetected(
+                    "None of the members confidently detected a pitch".to_string(),
+                )
+            })
+    }
+
+    /// Consensus frequency and agreement of the last estimates for `candidate`, whose score is `score`.
+    fn consensus(&self, candidate: f64, score: f64) -> (f64, f64) {
+        let weighted_log2_freq: f64 = self
+            .estimates
+            .iter()
+            .filter_map(|(freq, weight, confidence)| {
+                let freq = (*freq)?;
+                let offset = self.octave_offset(freq, candidate)?;
+                Some(weight * confidence * self.support(offset) * (freq.log2() - offset as f64))
+            })
+            .sum();
+        let total_weight: f64 = self.estimates.iter().map(|(_, weight, _)| weight).sum();
+        (2f64.powf(weighted_log2_freq / score), score / total_weight)
+    }
+}
+
+impl Default for EnsembleDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for EnsembleDetector {
+    /// The confidence is the agreement between the members, weighted by their confidences.
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        let mut samples = std::mem::take(&mut self.samples);
+        let signal = S::to_f64_signal(signal, &mut samples);
+        let detection = self
+            .vote(signal, sample_rate, freq_range)
+            .map(|(candidate, score)| {
+                let (freq, agreement) = self.consensus(candidate, score);
+                PitchDetection::new(freq, agreement, signal)
+            });
+        self.samples = samples;
+        detection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{
+            constants::{MAX_FREQ, MIN_FREQ},
+            test_utils::{test_confidence, test_freq, test_signal, test_sine_wave},
+        },
+        pitch::{Autocorrelation2, HannedFftDetector, HpsDetector, McLeodDetector, PowerCepstrum},
+    };
+
+    fn test_ensemble() -> EnsembleDetector {
+        EnsembleDetector::new()
+            .with_member(Box::new(HannedFftDetector::default()), 1.)
+            .with_member(Box::new(PowerCepstrum::new_with_defaults()), 1.)
+            .with_member(Box::new(Autocorrelation2::new(0.1)), 1.)
+            .with_member(Box::new(McLeodDetector::default()), 1.)
+            .with_member(Box::new(HpsDetector::default()), 1.)
+    }
+
+    test_freq! {tuner_c5: {
+        detector: test_ensemble(),
+        file: "tuner_c5.wav",
+        expected_freq: 524.221
+    }}
+    test_freq! {cello_open_a: {
+        detector: test_ensemble(),
+        file: "cello_open_a.wav",
+        expected_freq: 219.835
+    }}
+    test_freq! {cello_open_d: {
+        detector: test_ensemble(),
+        file: "cello_open_d.wav",
+        expected_freq: 147.018
+    }}
+    test_freq! {cello_open_g: {
+        detector: test_ensemble(),
+        file: "cello_open_g.wav",
+        expected_freq: 97.482
+    }}
+    test_freq! {cello_open_c: {
+        detector: test_ensemble(),
+        file: "cello_open_c.wav",
+        expected_freq: 64.652
+    }}
+
+    #[test]
+    fn test_from_sine_wave() -> anyhow::Result<()> {
+        let mut detector = test_ensemble();
+        test_sine_wave(&mut detector, 440.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_octave_errors() -> anyhow::Result<()> {
+        let mut detector = test_ensemble();
+        let signal = test_signal("cello_open_c.wav")?;
+        let detection =
+            detector.detect_with_breakdown_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        assert_eq!(detection.votes.len(), 5);
+        // HannedFftDetector detects the octave above open C
+        assert_eq!(detection.votes[0].octave_offset, Some(1));
+        assert!(detection.agreement > 0.5 && detection.agreement < 1.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_confidence_of_noise() -> anyhow::Result<()> {
+        let mut detector = test_ensemble();
+        test_confidence(&mut detector)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_has_no_confidence_in_silence() {
+        let mut detector =
+            EnsembleDetector::new().with_member(Box::new(HannedFftDetector::default()), 1.);
+        match detector.detect_with_confidence_in_range(&[0.; 4096], 44100., MIN_FREQ..MAX_FREQ) {
+            Ok(detection) => assert!(
+                detection.confidence < 0.1,
+                "Expected low confidence in silence, got {}",
+                detection.confidence
+            ),
+            Err(PitchError::NoPitchDetected(_)) => {}
+            Err(err) => panic!("Expected silence to have no pitch, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn weights_resolve_disagreements() -> anyhow::Result<()> {
+        let signal = test_signal("tuner_c5.wav")?;
+        let mut detector = EnsembleDetector::new()
+            .with_member(Box::new(HannedFftDetector::default()), 1.)
+            .with_member(Box::new(Autocorrelation2::new(0.1)), 3.);
+        let freq = detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        assert!((freq - 47.675).abs() < 0.1);
+
+        let mut detector = EnsembleDetector::new()
+            .with_member(Box::new(HannedFftDetector::default()), 3.)
+            .with_member(Box::new(Autocorrelation2::new(0.1)), 1.);
+        let freq = detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        assert!((freq - 524.431).abs() < 0.1);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "Member weight must be finite and positive")]
+    fn it_rejects_negative_weights() {
+        EnsembleDetector::new().with_member(Box::new(McLeodDetector::default()), -1.);
+    }
+
+    #[test]
+    #[should_panic(expected = "Member weight must be finite and positive")]
+    fn it_rejects_zero_weights() {
+        EnsembleDetector::new().with_member(Box::new(McLeodDetector::default()), 0.);
+    }
+
+    #[test]
+    #[should_panic(expected = "Member weight must be finite and positive")]
+    fn it_rejects_nan_weights() {
+        EnsembleDetector::new().with_member(Box::new(McLeodDetector::default()), f64::NAN);
+    }
+
+    #[test]
+    #[should_panic(expected = "Member weight must be finite and positive")]
+    fn it_rejects_infinite_weights() {
+        EnsembleDetector::new().with_member(Box::new(McLeodDetector::default()), f64::INFINITY);
+    }
+
+    #[test]
+    fn it_requires_members() {
+        let mut detector = EnsembleDetector::new();
+        assert!(matches!(
+            detector.detect_pitch_in_range(&[0.; 1024], 44100., MIN_FREQ..MAX_FREQ),
+            Err(PitchError::IncorrectParameters(_))
+        ));
+    }
+}
@@ -0,0 +1,370 @@
+use std::ops::Range;
+
+use crate::{
+    core::{
+        constants::{MAX_FREQ, MIN_FREQ, NOTES},
+        error::PitchError,
+        sample::Sample,
+        utils::{goertzel_magnitude, parabolic_interpolation, rms},
+        window::Window,
+        NoteName,
+    },
+    note::{
+        hinted::HintedNoteDetector,
+        tuning::{A4_KEY, NUM_KEYS},
+        NoteDetection, Tuning,
+    },
+};
+
+use super::{PitchDetection, PitchDetector};
+
+/// Distance between the probes used to find the best matching target, in cents. Three probes per target cover
+/// [MAX_CENTS_FROM_TARGET] on either side of it.
+const COARSE_CENTS_STEP: f64 = 100. / 3.;
+
+/// Maximum distance from a target at which the signal is probed, in cents
+const MAX_CENTS_FROM_TARGET: f64 = 50.;
+
+/// Maximum number of times that the fine probes are moved towards the peak
+const MAX_REFINEMENTS: usize = 4;
+
+/// Bank of Goertzel filters tuned around a fixed set of target frequencies and their harmonics. Useful for tuning
+/// to a known set of notes, such as the open strings of an instrument, since only a handful of frequencies are
+/// evaluated instead of a full spectrum: three probes per target to find the best one, and a few more to refine the
+/// frequency around it. Each probe runs one Goertzel filter per harmonic over the whole signal, so an FFT is cheaper
+/// when there are many targets or harmonics.
+#[derive(Debug, Clone)]
+pub struct GoertzelDetector {
+    /// Frequencies of the notes that are expected in the signal
+    targets: Vec<f64>,
+
+    /// Number of harmonics (including the fundamental) whose magnitudes are summed to score a frequency. Each harmonic
+    /// is weighted by the inverse of its number, so that a target whose fundamental is in the signal scores higher
+    /// than a target an octave or a twelfth below it
+    harmonics: usize,
+
+    /// Resolution of the final estimate, in cents, before interpolation
+    cents_step: f64,
+
+    /// How many times the power of the best probe must exceed the mean power of the spectrum, per harmonic, for the
+    /// signal to be considered to have a pitch at one of the targets
+    min_prominence: f64,
+
+    /// Hann windowed signal
+    windowed: Vec<f64>,
+}
+
+impl GoertzelDetector {
+    pub fn new(targets: Vec<f64>) -> Self {
+        Self {
+            targets,
+            harmonics: 3,
+            cents_step: 5.,
+            min_prominence: 10.,
+            windowed: vec![],
+        }
+    }
+
+    pub fn with_harmonics(self, harmonics: usize) -> Self {
+        Self { harmonics, ..self }
+    }
+
+    pub fn with_cents_step(self, cents_step: f64) -> Self {
+        Self { cents_step, ..self }
+    }
+
+    pub fn with_min_prominence(self, min_prominence: f64) -> Self {
+        Self {
+            min_prominence,
+            ..self
+        }
+    }
+
+    fn window_signal<S: Sample>(&mut self, signal: &[S]) {
+        self.windowed.clear();
+        self.windowed.extend(
+            signal
+                .iter()
+                .enumerate()
+                .map(|(i, s)| s.to_f64() * Window::Hann.value(i, signal.len())),
+        );
+    }
+
+    /// Weighted sum of the magnitudes of the windowed signal at the harmonics of `freq`.
+    fn score(&self, freq: f64, sample_rate: f64) -> f64 {
+        (1..=self.harmonics.max(1))
+            .map(|h| (h as f64, h as f64 * freq))
+            .take_while(|(_, f)| *f < sample_rate / 2.)
+            .map(|(h, f)| goertzel_magnitude(self.windowed.iter().copied(), f, sample_rate) / h)
+            .sum()
+    }
+
+    /// How many times the power of `score` exceeds the mean power of the spectrum, per harmonic. The mean power of the
+    /// bins of a Hann windowed signal is 3/8 of its energy, by Parseval's theorem.
+    fn prominence(&self, score: f64, signal_energy: f64) -> f64 {
+        let mean_power = 3. / 8. * signal_energy;
+        let total_weight: f64 = (1..=self.harmonics.max(1)).map(|h| 1. / h as f64).sum();
+        (score / total_weight).powi(2) / mean_power
+    }
+
+    /// Finds the target that best matches the windowed signal, and refines the frequency within
+    /// [MAX_CENTS_FROM_TARGET] of it. The confidence is based on how much the best probe stands out from the rest of
+    /// the coarse probes.
+    fn detect_pitch_from_targets<S: Sample>(
+        &self,
+        signal: &[S],
+        sample_rate: f64,
+        targets: impl Iterator<Item = f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        let at_cents = |target: f64, cents: f64| target * 2f64.powf(cents / 1200.);
+
+        let mut best: Option<(f64, f64, f64)> = None;
+        let (mut sum, mut count) = (0., 0);
+        for target in targets {
+            for step in -1..=1 {
+                let cents = step as f64 * COARSE_CENTS_STEP;
+                let score = self.score(at_cents(target, cents), sample_rate);
+                sum += score;
+                count += 1;
+                if best.is_none_or(|(_, _, best_score)| score > best_score) {
+                    best = Some((target, cents, score));
+                }
+            }
+        }
+        let (target, coarse_cents, score) = best.ok_or_else(|| {
+            PitchError::IncorrectParameters(
+                "No target frequencies within the frequency range".to_string(),
+            )
+        })?;
+        let signal_energy = self.windowed.len() as f64 * rms(signal).powi(2);
+        let prominence = self.prominence(score, signal_energy);
+        if score <= 0. || prominence < self.min_prominence {
+            return Err(PitchError::NoPitchDetected(format!(
+                "Signal has too little energy at the targets, best prominence was {:.1}",
+                prominence
+            )));
+        }
+
+        // Parabola through the best coarse probe and its neighbors, which may be beyond the coarse probes of the target
+        let score_at = |cents: f64| self.score(at_cents(target, cents), sample_rate);
+        let coarse = [
+            score_at(coarse_cents - COARSE_CENTS_STEP),
+            score,
+            score_at(coarse_cents + COARSE_CENTS_STEP),
+        ];
+        let mut cents = coarse_cents
+            + (parabolic_interpolation(&coarse, 1).x - 1.).clamp(-1., 1.) * COARSE_CENTS_STEP;
+
+        // Fine probes around the estimate, which are moved until the middle one is the highest
+        let mut fine = [0.; 3];
+        for _ in 0..MAX_REFINEMENTS {
+            cents = cents.clamp(-MAX_CENTS_FROM_TARGET, MAX_CENTS_FROM_TARGET);
+            for (i, score) in fine.iter_mut().enumerate() {
+                *score = score_at(cents + (i as f64 - 1.) * self.cents_step);
+            }
+            if fine[1] >= fine[0] && fine[1] >= fine[2] {
+                break;
+            }
+            cents += if fine[0] > fine[2] {
+                -self.cents_step
+            } else {
+                self.cents_step
+            };
+        }
+        let offset = (parabolic_interpolation(&fine, 1).x - 1.).clamp(-1., 1.);
+        let cents =
+            (cents + offset * self.cents_step).clamp(-MAX_CENTS_FROM_TARGET, MAX_CENTS_FROM_TARGET);
+        Ok(PitchDetection::new(
+            at_cents(target, cents),
+            1. - sum / count as f64 / score,
+            signal,
+        ))
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for GoertzelDetector {
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        self.window_signal(signal);
+        let targets = self
+            .targets
+            .iter()
+            .cloned()
+            .filter(|t| freq_range.contains(t));
+        self.detect_pitch_from_targets(signal, sample_rate, targets)
+    }
+}
+
+impl HintedNoteDetector for GoertzelDetector {
+    /// Only the targets that match the hint are evaluated. If none of the targets match the hint, every octave of the
+    /// hinted note within the frequency range is evaluated instead.
+    fn detect_note_with_hint_and_tuning<S: Sample>(
+        &mut self,
+        note_hint: NoteName,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range_hint: Option<Range<f64>>,
+        tuning: &Tuning,
+    ) -> Result<NoteDetection, PitchError> {
+        let freq_range = freq_range_hint.unwrap_or(MIN_FREQ..MAX_FREQ);
+        self.window_signal(signal);
+        let targets = self
+            .targets
+            .iter()
+            .cloned()
+            .filter(|t| freq_range.contains(t))
+            .filter(|t| matches!(tuning.freq_to_note(*t), Ok(note) if note.note_name == note_hint));
+        let detection = if targets.clone().next().is_some() {
+            self.detect_pitch_from_targets(signal, sample_rate, targets)
+        } else {
+            // Every note of the keyboard, from A4 down to the lowest key and up to the highest one
+            let targets = (-A4_KEY..NUM_KEYS - A4_KEY)
+                .filter(|step| {
+                    NoteName::from(NOTES[step.rem_euclid(NOTES.len() as i32) as usize]) == note_hint
+                })
+                .filter_map(|step| tuning.step_freq(step))
+                .filter(|t| freq_range.contains(t));
+            self.detect_pitch_from_targets(signal, sample_rate, targets)
+        };
+        detection.and_then(|detection| tuning.to_note(detection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        constants::{CELLO_TUNING, GUITAR_STANDARD_TUNING},
+        test_utils::{test_freq, test_signal},
+        utils::{mixed_wave_signal, sine_wave_signal},
+    };
+    use float_cmp::ApproxEq;
+
+    test_freq! {tuner_c5: {
+        detector: GoertzelDetector::new(vec![261.63, 523.25, 1046.5]),
+        file: "tuner_c5.wav",
+        expected_freq: 524.473
+    }}
+    test_freq! {cello_open_a: {
+        detector: GoertzelDetector::new(CELLO_TUNING.to_vec()),
+        file: "cello_open_a.wav",
+        expected_freq: 219.847
+    }}
+    test_freq! {cello_open_d: {
+        detector: GoertzelDetector::new(CELLO_TUNING.to_vec()),
+        file: "cello_open_d.wav",
+        expected_freq: 147.057
+    }}
+    test_freq! {cello_open_g: {
+        detector: GoertzelDetector::new(CELLO_TUNING.to_vec()),
+        file: "cello_open_g.wav",
+        expected_freq: 97.442
+    }}
+    test_freq! {cello_open_c: {
+        detector: GoertzelDetector::new(CELLO_TUNING.to_vec()),
+        file: "cello_open_c.wav",
+        expected_freq: 64.699
+    }}
+
+    #[test]
+    fn it_detects_out_of_tune_strings() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let mut detector = GoertzelDetector::new(GUITAR_STANDARD_TUNING.to_vec());
+        for freq in [80., 112., 148., 193., 250., 325.] {
+            let signal = sine_wave_signal(16384, freq, SAMPLE_RATE);
+            let actual_freq =
+                detector.detect_pitch_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+            assert!(
+                actual_freq.approx_eq(freq, (0.2, 2)),
+                "Expected freq: {}, Actual freq: {}",
+                freq,
+                actual_freq
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hinted_detector() -> anyhow::Result<()> {
+        let mut detector = GoertzelDetector::new(CELLO_TUNING.to_vec());
+        for (file, note) in [
+            ("cello_open_a.wav", NoteName::A),
+            ("cello_open_d.wav", NoteName::D),
+            ("cello_open_g.wav", NoteName::G),
+            ("cello_open_c.wav", NoteName::C),
+        ] {
+            let signal = test_signal(file)?;
+            let detection = detector.detect_note_with_hint_and_range(
+                note,
+                &signal,
+                44100.,
+                Some(MIN_FREQ..MAX_FREQ),
+            )?;
+            assert_eq!(detection.note_name, note);
+        }
+
+        // Without matching targets, every octave of the hinted note is evaluated
+        let mut detector = GoertzelDetector::new(vec![]);
+        let signal = test_signal("tuner_c5.wav")?;
+        let detection = detector.detect_note_with_hint_and_range(
+            NoteName::C,
+            &signal,
+            44100.,
+            Some(MIN_FREQ..MAX_FREQ),
+        )?;
+        assert_eq!(detection.note_name, NoteName::C);
+        assert_eq!(detection.octave, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_mixed_wave_signal() -> anyhow::Result<()> {
+        // None of the targets is an A, so every octave of A is evaluated
+        let mut detector = GoertzelDetector::new(vec![]);
+        let signal = mixed_wave_signal(16384, vec![440., 523.25], 44100.);
+        let detection = detector.detect_note_with_hint_and_range(
+            NoteName::A,
+            &signal,
+            44100.,
+            Some(MIN_FREQ..MAX_FREQ),
+        )?;
+        assert_eq!(detection.note_name, NoteName::A);
+        assert_eq!(detection.octave, 4);
+        assert!(detection.note_freq.approx_eq(440., (0.01, 2)));
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_signals_without_energy_at_the_targets() -> anyhow::Result<()> {
+        let mut detector = GoertzelDetector::new(CELLO_TUNING.to_vec());
+        for signal in [
+            sine_wave_signal(16384, 1000., 44100.),
+            test_signal("noise.wav")?,
+        ] {
+            assert!(matches!(
+                detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ),
+                Err(PitchError::NoPitchDetected(_))
+            ));
+        }
+
+        // The only A of the guitar is two octaves below the signal, and no C is close to it
+        let signal = sine_wave_signal(16384, 440., 44100.);
+        let mut detector = GoertzelDetector::new(GUITAR_STANDARD_TUNING.to_vec());
+        for note in [NoteName::A, NoteName::C] {
+            assert!(matches!(
+                detector.detect_note_with_hint_and_range(
+                    note,
+                    &signal,
+                    44100.,
+                    Some(MIN_FREQ..MAX_FREQ)
+                ),
+                Err(PitchError::NoPitchDetected(_))
+            ));
+        }
+        Ok(())
+    }
+}
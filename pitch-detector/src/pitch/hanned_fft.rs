@@ -1,31 +1,83 @@
+use std::f64::consts::PI;
 use std::ops::Range;
+use std::sync::Arc;
 
 use crate::core::error::PitchError;
-use crate::core::fft_space::FftSpace;
-use crate::core::utils::interpolated_peak_at;
+use crate::core::fft_space::{FftSpace, Padding};
+use crate::core::peak_interpolator::{GaussianInterpolator, PeakInterpolator};
+use crate::core::sample::Sample;
+use crate::core::utils::peak_to_mean_confidence;
+use crate::core::window::Window;
 use crate::core::FftPoint;
 use crate::pitch::ToFrequencyDomain;
-use rustfft::FftPlanner;
 
-use super::PitchDetector;
+use super::{PitchDetection, PitchDetector};
 
-#[derive(Debug, Clone, Default)]
+/// With the phase vocoder, the second frame starts this fraction of the signal after the first one
+const PHASE_VOCODER_HOP_DIVISOR: usize = 4;
+
+/// Detects the pitch from the highest peak of the spectrum of the signal. Despite its name, the signal can be tapered
+/// with any [Window] before the FFT, Hann being the default.
+///
+/// With the phase vocoder enabled, the signal is split in two overlapping frames, and the frequency of the peak is
+/// estimated from how much its phase advances from one frame to the next. This is much more accurate than
+/// interpolating the peak when the signal is short and the bins are several Hz apart.
+#[derive(Debug, Clone)]
 pub struct HannedFftDetector {
     fft_space: Option<FftSpace>,
+
+    /// Window applied to the signal before the FFT
+    window: Window,
+
+    /// Zero padding of the signal before the FFT
+    padding: Padding,
+
+    /// Whether to refine the frequency of the peak with the phase vocoder
+    phase_vocoder: bool,
+
+    /// Interpolator of the peak of the spectrum when the phase vocoder is not used
+    interpolator: Arc<dyn PeakInterpolator>,
+
+    /// Spectrum of the last signal
+    spectrum: Vec<f64>,
 }
 
 impl HannedFftDetector {
-    fn unscaled_spectrum(&self, bin_range: (usize, usize)) -> Box<dyn Iterator<Item = f64> + '_> {
+    pub fn with_window(self, window: Window) -> Self {
+        Self { window, ..self }
+    }
+
+    pub fn with_padding(self, padding: Padding) -> Self {
+        Self {
+            padding,
+            fft_space: None,
+            ..self
+        }
+    }
+
+    pub fn with_phase_vocoder(self, phase_vocoder: bool) -> Self {
+        Self {
+            phase_vocoder,
+            ..self
+        }
+    }
+
+    pub fn with_interpolator<I: PeakInterpolator + 'static>(self, interpolator: I) -> Self {
+        Self {
+            interpolator: Arc::new(interpolator),
+            ..self
+        }
+    }
+
+    fn unscaled_spectrum(&self, bin_range: (usize, usize)) -> impl Iterator<Item = f64> + '_ {
         if let Some(ref fft_space) = self.fft_space {
             let (lower_limit, upper_limit) = bin_range;
             let normalize = 1. / (fft_space.padded_len() as f64).sqrt();
-            Box::new(
-                fft_space
-                    .freq_domain_iter(true)
-                    .skip(lower_limit)
-                    .take(upper_limit - lower_limit)
-                    .map(move |(amplitude, _)| amplitude * normalize),
-            )
+            fft_space
+                .freq_domain_iter(true)
+                .skip(lower_limit)
+                .take(upper_limit - lower_limit)
+                .map(move |(amplitude, _)| amplitude * normalize)
         } else {
             panic!("FFT space not initialized");
         }
@@ -33,34 +85,28 @@ impl HannedFftDetector {
 
     fn process_fft(&mut self) {
         if let Some(ref mut fft_space) = self.fft_space {
-            let mut planner = FftPlanner::new();
-            let fft_len = fft_space.padded_len();
-            let signal_len = fft_space.signal_len();
-            let fft = planner.plan_fft_forward(fft_len);
-
-            let (space, scratch) = fft_space.workspace();
-            let hanning = apodize::hanning_iter(signal_len);
-            space.iter_mut().zip(hanning).for_each(|(s, h)| s.re *= h);
-            fft.process_with_scratch(space, scratch);
+            fft_space.apply_window(self.window);
+            fft_space.forward_fft();
         } else {
             panic!("fft_space is None");
         }
     }
-}
 
-impl ToFrequencyDomain for HannedFftDetector {
-    fn to_frequency_domain(
+    /// Computes the spectrum of the signal into `self.spectrum`, and returns the bin at which it starts.
+    fn compute_spectrum<S: Sample>(
         &mut self,
-        signal: &[f64],
+        signal: &[S],
         freq_range: Option<(Range<f64>, f64)>,
-    ) -> (usize, Vec<f64>) {
-        if self.fft_space.is_none() {
-            self.fft_space = Some(FftSpace::new(signal.len()));
+    ) -> usize {
+        let padding = self.padding;
+        let fft_space = self
+            .fft_space
+            .get_or_insert_with(|| FftSpace::new_padded(signal.len(), padding));
+        if fft_space.signal_len() != signal.len() {
+            fft_space.resize(signal.len());
         }
-        self.fft_space
-            .as_mut()
-            .unwrap()
-            .init_with_signal(signal.iter());
+        fft_space.init_with_signal(signal.iter());
+        let padded_len = fft_space.padded_len();
         self.process_fft();
         let bin_range = match freq_range {
             Some((r, sample_rate)) => (
@@ -68,9 +114,79 @@ impl ToFrequencyDomain for HannedFftDetector {
                 self.freq_to_bin(r.end, sample_rate).round() as usize,
             ),
             // The first half of the fft spectrum is conventionally the only important part.
-            None => (0, signal.len() / 2),
+            None => (0, padded_len / 2),
+        };
+        let mut spectrum = std::mem::take(&mut self.spectrum);
+        spectrum.clear();
+        spectrum.extend(self.unscaled_spectrum(bin_range));
+        self.spectrum = spectrum;
+        bin_range.0
+    }
+
+    fn phase_at(&self, bin: usize) -> f64 {
+        if let Some(ref fft_space) = self.fft_space {
+            let (_, phase) = fft_space
+                .freq_domain_iter(true)
+                .nth(bin)
+                .expect("Bin should be within the spectrum");
+            phase
+        } else {
+            panic!("fft_space is None");
+        }
+    }
+
+    /// Estimates the frequency of the peak at `bin` of the spectrum of the first frame of `signal`, which must be the
+    /// last one computed, from its phase advance to the frame starting `hop` samples later.
+    fn phase_vocoder_freq<S: Sample>(
+        &mut self,
+        signal: &[S],
+        hop: usize,
+        bin: usize,
+        sample_rate: f64,
+    ) -> f64 {
+        let phase = self.phase_at(bin);
+        let padded_len = if let Some(ref mut fft_space) = self.fft_space {
+            fft_space.init_with_signal(signal[hop..].iter());
+            fft_space.padded_len() as f64
+        } else {
+            panic!("fft_space is None");
         };
-        (bin_range.0, self.unscaled_spectrum(bin_range).collect())
+        self.process_fft();
+        let next_phase = self.phase_at(bin);
+
+        // The phase of a sine exactly on the bin advances by this much. Any deviation from it is due to the sine
+        // being off the bin, which is only unambiguous as long as the deviation is within half a turn.
+        let bin_advance = 2. * PI * bin as f64 * hop as f64 / padded_len;
+        let deviation = (next_phase - phase - bin_advance + PI).rem_euclid(2. * PI) - PI;
+        let angular_freq = 2. * PI * bin as f64 / padded_len + deviation / hop as f64;
+        angular_freq * sample_rate / (2. * PI)
+    }
+}
+
+impl Default for HannedFftDetector {
+    fn default() -> Self {
+        Self {
+            fft_space: None,
+            window: Window::Hann,
+            padding: Padding::default(),
+            phase_vocoder: false,
+            interpolator: Arc::new(GaussianInterpolator),
+            spectrum: vec![],
+        }
+    }
+}
+
+impl ToFrequencyDomain for HannedFftDetector {
+    fn compute_frequency_domain<S: Sample>(
+        &mut self,
+        signal: &[S],
+        freq_range: Option<(Range<f64>, f64)>,
+    ) -> usize {
+        self.compute_spectrum(signal, freq_range)
+    }
+
+    fn frequency_domain(&self) -> &[f64] {
+        &self.spectrum
     }
 
     fn bin_to_freq(&self, bin: f64, sample_rate: f64) -> f64 {
@@ -87,34 +203,69 @@ impl ToFrequencyDomain for HannedFftDetector {
             panic!("RawFftDetector needs to be initialized with a FftSpace first");
         }
     }
+
+    fn peak_interpolator(&self) -> &dyn PeakInterpolator {
+        self.interpolator.as_ref()
+    }
 }
 
-impl PitchDetector for HannedFftDetector {
-    fn detect_pitch_in_range(
+impl<S: Sample> PitchDetector<S> for HannedFftDetector {
+    /// The confidence is based on the ratio of the peak of the spectrum to its mean.
+    fn detect_with_confidence_in_range(
         &mut self,
-        signal: &[f64],
+        signal: &[S],
         sample_rate: f64,
         freq_range: Range<f64>,
-    ) -> Result<f64, PitchError> {
-        let (start_bin, spectrum) =
-            self.to_frequency_domain(signal, Some((freq_range, sample_rate)));
-        let max_bin = spectrum
+    ) -> Result<PitchDetection, PitchError> {
+        // The phase advance is unambiguous for frequencies up to a bin away from the peak as long as the hop is shorter
+        // than the frame
+        let hop = if self.phase_vocoder {
+            signal.len() / PHASE_VOCODER_HOP_DIVISOR
+        } else {
+            0
+        };
+        let frame = &signal[..signal.len() - hop];
+        let start_bin = self.compute_spectrum(frame, Some((freq_range, sample_rate)));
+        let spectrum = &self.spectrum;
+        let (max_bin, max_value) = spectrum
             .iter()
             .enumerate()
             .reduce(|accum, item| if item.1 > accum.1 { item } else { accum })
-            .ok_or(PitchError::IncorrectParameters(
-                "Spectrum had no elements".to_string(),
-            ))?;
+            .ok_or_else(|| {
+                PitchError::IncorrectParameters("Spectrum had no elements".to_string())
+            })?;
+        let confidence = peak_to_mean_confidence(spectrum, *max_value);
 
-        let FftPoint { x: bin, .. } = interpolated_peak_at(&spectrum, max_bin.0)?;
-        Ok(self.bin_to_freq(bin + start_bin as f64, sample_rate))
+        let freq = if hop > 0 {
+            self.phase_vocoder_freq(signal, hop, max_bin + start_bin, sample_rate)
+        } else {
+            let bins = self
+                .fft_space
+                .as_ref()
+                .map(|fft_space| &fft_space.space()[start_bin..]);
+            let FftPoint { x: bin, .. } = self
+                .interpolator
+                .interpolate_peak(spectrum, bins, max_bin)?;
+            self.bin_to_freq(bin + start_bin as f64, sample_rate)
+        };
+        Ok(PitchDetection::new(freq, confidence, signal))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::test_utils::{test_freq, test_sine_wave};
+    use crate::core::test_utils::{
+        test_changing_signal_lengths, test_confidence, test_freq, test_sample_types, test_signal,
+        test_sine_wave,
+    };
+    use crate::core::{
+        constants::{MAX_FREQ, MIN_FREQ},
+        peak_interpolator::{
+            JainInterpolator, LogParabolicInterpolator, QuadraticInterpolator, QuinnInterpolator,
+        },
+        utils::sine_wave_signal,
+    };
 
     test_freq! {tuner_c5: {
         detector: HannedFftDetector::default(),
@@ -148,4 +299,162 @@ mod tests {
         test_sine_wave(&mut detector, 440.)?;
         Ok(())
     }
+
+    #[test]
+    fn test_peak_to_mean_confidence() -> anyhow::Result<()> {
+        let mut detector = HannedFftDetector::default();
+        test_confidence(&mut detector)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_other_sample_types() -> anyhow::Result<()> {
+        let mut detector = HannedFftDetector::default();
+        test_sample_types(&mut detector)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reused_with_changing_signal_lengths() -> anyhow::Result<()> {
+        let mut detector = HannedFftDetector::default();
+        test_changing_signal_lengths(&mut detector)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows_have_comparable_magnitudes() {
+        const SAMPLE_RATE: f64 = 44100.0;
+        // Exactly on bin 1000 of the spectrum
+        let freq = 1000. * SAMPLE_RATE / 16384.;
+        let signal = sine_wave_signal(16384, freq, SAMPLE_RATE);
+        let peak_magnitude = |window: Window| {
+            let mut detector = HannedFftDetector::default().with_window(window);
+            let (_, spectrum) = detector.to_frequency_domain(&signal, None);
+            spectrum.iter().cloned().reduce(f64::max).unwrap()
+        };
+        let expected_magnitude = peak_magnitude(Window::Rectangular);
+        for window in [
+            Window::Hann,
+            Window::Hamming,
+            Window::Blackman,
+            Window::BlackmanHarris,
+            Window::Kaiser(8.),
+            Window::FlatTop,
+            Window::Gaussian(0.4),
+        ] {
+            let magnitude = peak_magnitude(window);
+            assert!(
+                (magnitude / expected_magnitude - 1.).abs() < 0.01,
+                "{:?} has a peak magnitude of {}, expected {}",
+                window,
+                magnitude,
+                expected_magnitude
+            );
+        }
+    }
+
+    #[test]
+    fn test_recording_with_windows() -> anyhow::Result<()> {
+        let signal = test_signal("cello_open_a.wav")?;
+        // Flat-top windows have flat peaks, which are meant for measuring amplitudes rather than frequencies
+        for window in [Window::Blackman, Window::BlackmanHarris, Window::Kaiser(8.)] {
+            let mut detector = HannedFftDetector::default().with_window(window);
+            let freq = detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+            assert!((freq - 219.885).abs() < 0.1, "{:?}: {}", window, freq);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_padding_short_buffers() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let signal = sine_wave_signal(1024, 440., SAMPLE_RATE);
+        let mut detector = HannedFftDetector::default();
+        let unpadded_freq =
+            detector.detect_pitch_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+        let mut detector = HannedFftDetector::default().with_padding(Padding::new(8));
+        let padded_freq =
+            detector.detect_pitch_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+        assert!(
+            (padded_freq - 440.).abs() < (unpadded_freq - 440.).abs(),
+            "Padded: {}, unpadded: {}",
+            padded_freq,
+            unpadded_freq
+        );
+        assert!(
+            (padded_freq - 440.).abs() < 0.5,
+            "Actual freq: {}",
+            padded_freq
+        );
+        Ok(())
+    }
+
+    fn cents(freq: f64, expected_freq: f64) -> f64 {
+        1200. * (freq / expected_freq).log2()
+    }
+
+    #[test]
+    fn test_phase_vocoder_short_buffers() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        // Bins of 1024 samples are about 43 Hz apart
+        for expected_freq in [196., 261.63, 440., 987.77] {
+            let signal = sine_wave_signal(1024, expected_freq, SAMPLE_RATE);
+            let mut detector = HannedFftDetector::default().with_phase_vocoder(true);
+            let freq = detector.detect_pitch_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+            assert!(
+                cents(freq, expected_freq).abs() < 1.,
+                "Expected freq: {}, actual freq: {}",
+                expected_freq,
+                freq
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_phase_vocoder_recording() -> anyhow::Result<()> {
+        let signal = test_signal("cello_open_a.wav")?;
+        let mut detector = HannedFftDetector::default().with_phase_vocoder(true);
+        let freq = detector.detect_pitch_in_range(&signal[..2048], 44100., MIN_FREQ..MAX_FREQ)?;
+        assert!((freq - 220.).abs() < 0.5, "Actual freq: {}", freq);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpolators() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        // Halfway between two bins of a 4096 sample spectrum
+        let expected_freq = 40.5 * SAMPLE_RATE / 4096.;
+        let signal = sine_wave_signal(4096, expected_freq, SAMPLE_RATE);
+        let detectors = [
+            HannedFftDetector::default().with_interpolator(QuadraticInterpolator),
+            HannedFftDetector::default().with_interpolator(LogParabolicInterpolator),
+            HannedFftDetector::default().with_interpolator(GaussianInterpolator),
+            HannedFftDetector::default()
+                .with_window(Window::Rectangular)
+                .with_interpolator(JainInterpolator),
+            HannedFftDetector::default()
+                .with_window(Window::Rectangular)
+                .with_interpolator(QuinnInterpolator),
+        ];
+        for mut detector in detectors {
+            let freq = detector.detect_pitch_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+            assert!(
+                cents(freq, expected_freq).abs() < 5.,
+                "{:?}: expected freq: {}, actual freq: {}",
+                detector.interpolator,
+                expected_freq,
+                freq
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_power_of_two_padding() -> anyhow::Result<()> {
+        let mut detector =
+            HannedFftDetector::default().with_padding(Padding::new(3).with_power_of_two(false));
+        test_sine_wave(&mut detector, 440.)?;
+        Ok(())
+    }
 }
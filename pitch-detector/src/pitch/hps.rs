@@ -0,0 +1,188 @@
+use std::ops::Range;
+
+use crate::core::{
+    error::PitchError,
+    sample::Sample,
+    utils::{parabolic_interpolation, peak_to_mean_confidence},
+    FftPoint,
+};
+
+use super::{HannedFftDetector, PitchDetection, PitchDetector, ToFrequencyDomain};
+
+/// Harmonic Product Spectrum detector. The spectrum of the [HannedFftDetector] is downsampled by each of the first
+/// `harmonics` integer factors, and the downsampled spectra are multiplied together. Since the harmonics of a note
+/// line up at its fundamental, the product peaks at the fundamental even when the fundamental itself is weak, which
+/// is common with low strings.
+#[derive(Debug, Clone)]
+pub struct HpsDetector {
+    fft: HannedFftDetector,
+
+    /// Number of spectra (including the original one) that are multiplied together
+    harmonics: usize,
+
+    /// Harmonic product spectrum of the last signal
+    hps: Vec<f64>,
+}
+
+impl HpsDetector {
+    pub fn new(harmonics: usize) -> Self {
+        Self {
+            harmonics,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_harmonics(self, harmonics: usize) -> Self {
+        Self { harmonics, ..self }
+    }
+}
+
+impl Default for HpsDetector {
+    fn default() -> Self {
+        Self {
+            fft: HannedFftDetector::default(),
+            harmonics: 5,
+            hps: vec![],
+        }
+    }
+}
+
+impl ToFrequencyDomain for HpsDetector {
+    fn compute_frequency_domain<S: Sample>(
+        &mut self,
+        signal: &[S],
+        freq_range: Option<(Range<f64>, f64)>,
+    ) -> usize {
+        let harmonics = self.harmonics.max(1);
+
+        // The spectrum needs to start at bin 0 and reach the highest harmonic of the frequency range so that it can
+        // be downsampled.
+        let spectrum_range = freq_range.as_ref().map(|(r, sample_rate)| {
+            (
+                0.0..(r.end * harmonics as f64).min(sample_rate / 2.),
+                *sample_rate,
+            )
+        });
+        self.fft.compute_frequency_domain(signal, spectrum_range);
+        let spectrum = self.fft.frequency_domain();
+        let (start_bin, end_bin) = match freq_range {
+            Some((r, sample_rate)) => (
+                self.freq_to_bin(r.start, sample_rate).round() as usize,
+                self.freq_to_bin(r.end, sample_rate).round() as usize,
+            ),
+            None => (0, spectrum.len() / harmonics),
+        };
+
+        // Each downsampled bin keeps the maximum of the bins it covers, so that harmonics that fall between bins (or
+        // that are slightly inharmonic) still line up. Harmonics above the nyquist frequency are left out of the
+        // product.
+        let mut hps = std::mem::take(&mut self.hps);
+        hps.clear();
+        hps.extend((start_bin..end_bin).map(|bin| {
+            (1..=harmonics)
+                .filter_map(|h| {
+                    let center = h * bin;
+                    spectrum
+                        .get(center.saturating_sub(h / 2)..=center + h / 2)
+                        .or_else(|| spectrum.get(center..center + 1))
+                        .and_then(|bins| bins.iter().cloned().reduce(f64::max))
+                })
+                .product::<f64>()
+        }));
+        self.hps = hps;
+        start_bin
+    }
+
+    fn frequency_domain(&self) -> &[f64] {
+        &self.hps
+    }
+
+    fn bin_to_freq(&self, bin: f64, sample_rate: f64) -> f64 {
+        self.fft.bin_to_freq(bin, sample_rate)
+    }
+
+    fn freq_to_bin(&self, freq: f64, sample_rate: f64) -> f64 {
+        self.fft.freq_to_bin(freq, sample_rate)
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for HpsDetector {
+    /// The confidence is based on the ratio of the peak of the harmonic product spectrum to its mean.
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        let start_bin = self.compute_frequency_domain(signal, Some((freq_range, sample_rate)));
+        let spectrum = &self.hps;
+        let max_bin = spectrum
+            .iter()
+            .enumerate()
+            .reduce(|accum, item| if item.1 > accum.1 { item } else { accum })
+            .ok_or_else(|| {
+                PitchError::IncorrectParameters("Spectrum had no elements".to_string())
+            })?;
+
+        // The product of many spectra has very sharp peaks and very small values, which Gaussian fitting does not
+        // handle well
+        let FftPoint { x: bin, .. } = parabolic_interpolation(spectrum, max_bin.0);
+        Ok(PitchDetection::new(
+            self.bin_to_freq(bin + start_bin as f64, sample_rate),
+            peak_to_mean_confidence(spectrum, *max_bin.1),
+            signal,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        constants::{MAX_FREQ, MIN_FREQ},
+        test_utils::test_freq,
+        utils::mixed_wave_signal,
+    };
+    use float_cmp::ApproxEq;
+
+    test_freq! {tuner_c5: {
+        detector: HpsDetector::default(),
+        file: "tuner_c5.wav",
+        expected_freq: 524.649
+    }}
+    test_freq! {cello_open_a: {
+        detector: HpsDetector::default(),
+        file: "cello_open_a.wav",
+        expected_freq: 219.463
+    }}
+    test_freq! {cello_open_d: {
+        detector: HpsDetector::default(),
+        file: "cello_open_d.wav",
+        expected_freq: 146.849
+    }}
+    test_freq! {cello_open_g: {
+        detector: HpsDetector::default(),
+        file: "cello_open_g.wav",
+        expected_freq: 97.305
+    }}
+    test_freq! {cello_open_c: {
+        detector: HpsDetector::default(),
+        file: "cello_open_c.wav",
+        expected_freq: 64.614 // HannedFftDetector detects the octave, 129.334
+    }}
+
+    // HPS doesn't work with pure sine waves since it looks for a harmonic sequence.
+    #[test]
+    fn test_from_harmonic_waves() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let mut detector = HpsDetector::default();
+        let signal = mixed_wave_signal(16384, vec![110., 220., 330., 440., 550.], SAMPLE_RATE);
+        let freq = detector.detect_pitch_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+        assert!(
+            freq.approx_eq(110., (0.5, 0)),
+            "Expected freq: 110, Actual freq: {}",
+            freq
+        );
+        Ok(())
+    }
+}
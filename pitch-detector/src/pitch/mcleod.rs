@@ -0,0 +1,253 @@
+// Rust implementation of the McLeod Pitch Method (MPM)
+use std::ops::Range;
+
+use crate::core::{error::PitchError, sample::Sample, utils::parabolic_interpolation, FftPoint};
+
+use super::{PitchDetection, PitchDetector};
+
+/// Normalized square difference function, computed into `nsdf`. Each value is in the range [-1, 1], where 1 means
+/// that the signal is perfectly periodic with period `tau`.
+fn normalized_square_difference(signal: &[f64], max_lag: usize, nsdf: &mut Vec<f64>) {
+    let max_lag = max_lag.min(signal.len());
+    nsdf.clear();
+    nsdf.resize(max_lag, 0.0);
+
+    for (tau, value) in nsdf.iter_mut().enumerate() {
+        let mut acf = 0.0;
+        let mut energy = 0.0;
+        for i in 0..(signal.len() - tau) {
+            acf += signal[i] * signal[i + tau];
+            energy += signal[i] * signal[i] + signal[i + tau] * signal[i + tau];
+        }
+        *value = if energy > 0.0 {
+            2.0 * acf / energy
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Finds the highest maximum between each pair of positively sloped and negatively sloped zero crossings.
+/// The region before the first negatively sloped zero crossing is skipped, since it corresponds to the peak at
+/// `tau = 0`.
+fn key_maxima(nsdf: &[f64]) -> impl Iterator<Item = usize> + '_ {
+    let mut tau = 1;
+
+    // Skip the initial positive lobe
+    while tau < nsdf.len() && nsdf[tau] > 0.0 {
+        tau += 1;
+    }
+
+    std::iter::from_fn(move || {
+        let mut current_max: Option<usize> = None;
+        while tau < nsdf.len() {
+            if nsdf[tau] > 0.0 {
+                match current_max {
+                    Some(max_tau) if nsdf[max_tau] >= nsdf[tau] => {}
+                    _ => current_max = Some(tau),
+                }
+            } else if current_max.is_some() {
+                tau += 1;
+                return current_max;
+            }
+            tau += 1;
+        }
+        current_max
+    })
+}
+
+/// Main McLeod pitch detection function. The normalized square difference function is computed into `nsdf`, so that
+/// it can be reused across calls.
+pub fn mcleod_pitch(
+    nsdf: &mut Vec<f64>,
+    signal: &[f64],
+    sample_rate: f64,
+    freq_range: Range<f64>,
+    cutoff: f64,
+    clarity_threshold: f64,
+) -> Result<(f64, f64), PitchError> {
+    if freq_range.start <= 0.0 || freq_range.end <= freq_range.start {
+        return Err(PitchError::IncorrectParameters(format!(
+            "Invalid frequency range: {:?}",
+            freq_range
+        )));
+    }
+    if !(cutoff > 0.0 && cutoff <= 1.0) {
+        return Err(PitchError::IncorrectParameters(format!(
+            "Cutoff must be in the range (0, 1], got {}",
+            cutoff
+        )));
+    }
+    let min_lag = (sample_rate / freq_range.end).floor() as usize;
+    let max_lag = (sample_rate / freq_range.start).ceil() as usize + 2;
+
+    // Step 1: Compute the normalized square difference function
+    normalized_square_difference(signal, max_lag, nsdf);
+
+    // Step 2: Find the key maxima that correspond to the requested frequency range
+    let candidates = || {
+        key_maxima(nsdf)
+            .map(|tau| parabolic_interpolation(nsdf, tau))
+            .filter(|point| point.x >= min_lag as f64)
+    };
+    let highest = candidates()
+        .map(|point| point.y)
+        .reduce(f64::max)
+        .ok_or_else(|| {
+            PitchError::NoPitchDetected("Did not find any periodicity in the signal".to_string())
+        })?;
+
+    // Step 3: Choose the first key maximum that is close enough to the highest one
+    let FftPoint { x: tau, y: clarity } = candidates()
+        .find(|point| point.y >= cutoff * highest)
+        .ok_or_else(|| {
+            PitchError::NoPitchDetected("No key maximum passed the cutoff".to_string())
+        })?;
+    if clarity < clarity_threshold {
+        return Err(PitchError::NoPitchDetected(format!(
+            "Clarity {:.3} did not exceed threshold",
+            clarity
+        )));
+    }
+
+    let pitch = sample_rate / tau;
+    if freq_range.contains(&pitch) {
+        Ok((pitch, clarity))
+    } else {
+        Err(PitchError::NoPitchDetected(
+            "Detected pitch is outside of the frequency range".to_string(),
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct McLeodDetector {
+    /// Of the key maxima in the NSDF, the chosen one is the first whose value is at least `cutoff` times the
+    /// highest key maximum. Typical value is between 0.8 and 1.0, and it must be in the range (0, 1]
+    cutoff: f64,
+
+    /// Minimum NSDF value of the chosen key maximum for the signal to be considered pitched
+    clarity_threshold: f64,
+
+    /// Samples of the last signal converted to `f64` and its NSDF
+    samples: Vec<f64>,
+    nsdf: Vec<f64>,
+}
+
+impl McLeodDetector {
+    pub fn new(cutoff: f64, clarity_threshold: f64) -> Self {
+        Self {
+            cutoff,
+            clarity_threshold,
+            samples: vec![],
+            nsdf: vec![],
+        }
+    }
+
+    pub fn with_cutoff(self, cutoff: f64) -> Self {
+        Self { cutoff, ..self }
+    }
+
+    pub fn with_clarity_threshold(self, clarity_threshold: f64) -> Self {
+        Self {
+            clarity_threshold,
+            ..self
+        }
+    }
+}
+
+impl Default for McLeodDetector {
+    fn default() -> Self {
+        Self {
+            cutoff: 0.93,
+            clarity_threshold: 0.5,
+            samples: vec![],
+            nsdf: vec![],
+        }
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for McLeodDetector {
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        let signal = S::to_f64_signal(signal, &mut self.samples);
+        let (pitch, clarity) = mcleod_pitch(
+            &mut self.nsdf,
+            signal,
+            sample_rate,
+            freq_range,
+            self.cutoff,
+            self.clarity_threshold,
+        )?;
+        Ok(PitchDetection::new(pitch, clarity, signal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        test_utils::{test_freq, test_sample_types, test_sine_wave},
+        utils::sine_wave_signal,
+    };
+
+    test_freq! {tuner_c5: {
+        detector: McLeodDetector::default(),
+        file: "tuner_c5.wav",
+        expected_freq: 47.677 // The low frequency component of the tuner recording dominates the NSDF, as with Autocorrelation2
+    }}
+    test_freq! {cello_open_a: {
+        detector: McLeodDetector::default(),
+        file: "cello_open_a.wav",
+        expected_freq: 219.954
+    }}
+    test_freq! {cello_open_d: {
+        detector: McLeodDetector::default(),
+        file: "cello_open_d.wav",
+        expected_freq: 147.055
+    }}
+    test_freq! {cello_open_g: {
+        detector: McLeodDetector::default(),
+        file: "cello_open_g.wav",
+        expected_freq: 97.609
+    }}
+    test_freq! {cello_open_c: {
+        detector: McLeodDetector::default(),
+        file: "cello_open_c.wav",
+        expected_freq: 64.681
+    }}
+
+    #[test]
+    fn it_detects_sine_wave_pitch() -> anyhow::Result<()> {
+        let mut detector = McLeodDetector::default();
+        test_sine_wave(&mut detector, 440.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_other_sample_types() -> anyhow::Result<()> {
+        let mut detector = McLeodDetector::default();
+        test_sample_types(&mut detector)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_cutoffs_outside_of_the_unit_range() -> anyhow::Result<()> {
+        let signal = sine_wave_signal(4096, 440., 44100.);
+        for cutoff in [0., -0.5, 1.2] {
+            let mut detector = McLeodDetector::default().with_cutoff(cutoff);
+            assert!(matches!(
+                detector.detect_pitch(&signal, 44100.),
+                Err(PitchError::IncorrectParameters(_))
+            ));
+        }
+        // A cutoff of 1 always chooses the highest key maximum
+        let mut detector = McLeodDetector::default().with_cutoff(1.);
+        detector.detect_pitch(&signal, 44100.)?;
+        Ok(())
+    }
+}
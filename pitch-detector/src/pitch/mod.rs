@@ -2,36 +2,119 @@
 //! which is the most versatile. [PowerCepstrum](crate::pitch::cepstrum::PowerCepstrum) on the other hand, is less versatile,
 //! but it is able to detect a fundamental from a sample that includes many harmonics. This means that [PowerCepstrum](crate::pitch::cepstrum::PowerCepstrum)
 //! is good for detecting sounds that are rich in harmonics, as well as low pitched sounds, but bad at detecting samples
-//! with fewer partials.
+//! with fewer partials. [McLeodDetector](crate::pitch::McLeodDetector) implements the McLeod Pitch Method, which
+//! works in the time domain and is well suited for bowed strings and voice. [PYinDetector](crate::pitch::PYinDetector)
+//! tracks the pitch across the frames of a longer signal, and also reports the probability of each frame being voiced.
+//! [HpsDetector](crate::pitch::HpsDetector) uses the harmonics of the spectrum to detect low notes with a weak
+//! fundamental at the correct octave. [ShsDetector](crate::pitch::ShsDetector) scores every candidate frequency by
+//! the strength of its harmonics, and can return the ranked list of candidates for ambiguous notes.
+//! [SwipeDetector](crate::pitch::SwipeDetector) implements SWIPE', which is robust but slower than the other algorithms.
+//! [GoertzelDetector](crate::pitch::GoertzelDetector) only evaluates the frequencies around a fixed set of targets,
+//! such as the open strings of an instrument, which makes it very cheap for tuning to known notes.
+//! [ConstantQDetector](crate::pitch::ConstantQDetector) computes a spectrum with logarithmically spaced bins, which
+//! have the same musical resolution across octaves and can line up with semitones.
+//!
+//! Since every algorithm fails differently, several detectors can be combined with an
+//! [EnsembleDetector](crate::pitch::EnsembleDetector), which resolves their disagreements with octave-aware voting.
+//!
+//! Every detector can also report how confident it is in the detected pitch, along with the level of the signal,
+//! through [detect_with_confidence_in_range](crate::pitch::PitchDetector::detect_with_confidence_in_range).
+//!
+//! Detectors keep their intermediate buffers between calls, so once they have detected the pitch of a signal, they
+//! don't allocate when detecting the pitch of other signals of the same length, which makes them safe to use in
+//! real-time audio callbacks.
+//!
+//! To track the pitch of a stream of samples, such as the input of a microphone, any detector can be used with a
+//! [PitchTracker](crate::pitch::PitchTracker), which analyzes overlapping frames of the stream as chunks arrive.
+//!
+//! Whole recordings can be analyzed frame by frame with [pitch_contour](crate::pitch::pitch_contour), or with
+//! `par_pitch_contour` to analyze the frames in parallel when the `parallel` feature is enabled.
+//!
+//! Signals don't need to be converted to `f64` before detecting their pitch, since every detector accepts samples of
+//! any [Sample](crate::core::sample::Sample) type, such as `f32`, `i16` or `i32`.
+//!
+//! Any detector can be wrapped in a [VoicingGate](crate::pitch::VoicingGate), which rejects signals that look like
+//! noise based on their zero crossing rate, their energy and how much of it is mains hum.
 //!
 mod autocorrelation2;
 mod cepstrum;
 mod cepstrum2;
+mod constant_q;
+mod contour;
+mod ensemble;
+mod goertzel;
 mod hanned_fft;
+mod hps;
+mod mcleod;
+mod pitch_detection_result;
+mod pyin;
+mod shs;
+mod swipe;
+mod tracker;
+mod zero_crossing;
 
-pub use autocorrelation2::Autocorrelation2;
+pub use autocorrelation2::{Autocorrelation2, YinEstimate};
 pub use cepstrum::PowerCepstrum;
 pub use cepstrum2::Cepstrum2;
+pub use constant_q::ConstantQDetector;
+#[cfg(feature = "parallel")]
+pub use contour::par_pitch_contour;
+pub use contour::{pitch_contour, ContourFrame};
+pub use ensemble::{EnsembleDetection, EnsembleDetector, EnsembleVote};
+pub use goertzel::GoertzelDetector;
 pub use hanned_fft::HannedFftDetector;
+pub use hps::HpsDetector;
+pub use mcleod::McLeodDetector;
+pub use pitch_detection_result::PitchDetection;
+pub use pyin::{PYinCandidate, PYinDetector, PYinFrame};
+pub use shs::{ShsCandidate, ShsDetector};
+pub use swipe::SwipeDetector;
+pub use tracker::{PitchTracker, TrackedFrame};
+pub use zero_crossing::{VoicingGate, ZeroCrossingDetector};
 
 use std::ops::Range;
 
-use crate::core::{error::PitchError, into_frequency_domain::ToFrequencyDomain};
+use crate::core::{error::PitchError, into_frequency_domain::ToFrequencyDomain, sample::Sample};
 
-pub trait PitchDetector {
+/// Detects the pitch of signals with samples of type `S`, such as `f32` samples from an audio input device, or `i16`
+/// samples from a wav file. All the detectors in this crate accept every [Sample] type.
+pub trait PitchDetector<S: Sample = f64> {
     /// The default implementation will detect within a conventional range of frequencies (20Hz to nyquist).
     /// If you want to detect a pitch in a specific range, use the [detect_pitch_in_range](Self::detect_pitch_in_range) method
-    fn detect_pitch(&mut self, signal: &[f64], sample_rate: f64) -> Result<f64, PitchError> {
-        let nyquist_freq = sample_rate / 2.;
-        let min_freq = 20.; // Conventional minimum frequency for human hearing
-        self.detect_pitch_in_range(signal, sample_rate, min_freq..nyquist_freq)
+    fn detect_pitch(&mut self, signal: &[S], sample_rate: f64) -> Result<f64, PitchError> {
+        self.detect_with_confidence(signal, sample_rate)
+            .map(|detection| detection.freq)
     }
 
     /// Default implementation to detect a pitch within the specified frequency range.
     fn detect_pitch_in_range(
         &mut self,
-        signal: &[f64],
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<f64, PitchError> {
+        self.detect_with_confidence_in_range(signal, sample_rate, freq_range)
+            .map(|detection| detection.freq)
+    }
+
+    /// Same as [detect_pitch](Self::detect_pitch), but the result includes how confident the detector is in the
+    /// detected pitch, and the level of the signal.
+    fn detect_with_confidence(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+    ) -> Result<PitchDetection, PitchError> {
+        let nyquist_freq = sample_rate / 2.;
+        let min_freq = 20.; // Conventional minimum frequency for human hearing
+        self.detect_with_confidence_in_range(signal, sample_rate, min_freq..nyquist_freq)
+    }
+
+    /// Detects a pitch within the specified frequency range, along with how confident the detector is in the
+    /// detected pitch, and the level of the signal.
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
         sample_rate: f64,
         freq_range: Range<f64>,
-    ) -> Result<f64, PitchError>;
+    ) -> Result<PitchDetection, PitchError>;
 }
@@ -0,0 +1,32 @@
+use crate::core::{sample::Sample, utils::rms};
+
+/// The result of a pitch detection.
+/// Most commonly this will be returned from
+/// [`detect_with_confidence_in_range`](crate::pitch::PitchDetector::detect_with_confidence_in_range).
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct PitchDetection {
+    /// The predominant frequency detected from a signal.
+    pub freq: f64,
+
+    /// How confident the detector is that the signal has a pitch at `freq`, in the range [0, 1]. Every detector
+    /// measures confidence differently, so confidences of different detectors are not directly comparable.
+    pub confidence: f64,
+
+    /// The root mean square level of the signal, with integer samples normalized to the `[-1, 1]` range.
+    pub signal_level: f64,
+}
+
+impl PitchDetection {
+    /// Creates a detection with the given confidence, clamped to [0, 1], and the level of `signal`.
+    pub fn new<S: Sample>(freq: f64, confidence: f64, signal: &[S]) -> Self {
+        Self {
+            freq,
+            confidence: if confidence.is_nan() {
+                0.
+            } else {
+                confidence.clamp(0., 1.)
+            },
+            signal_level: rms(signal),
+        }
+    }
+}
@@ -0,0 +1,519 @@
+// Rust implementation of the probabilistic YIN (pYIN) pitch tracking algorithm
+use std::ops::Range;
+
+use crate::core::{
+    error::PitchError, fft_space::FftSpace, sample::Sample, utils::parabolic_interpolation,
+};
+
+use super::{
+    autocorrelation2::{cumulative_mean_normalized_difference, difference_function},
+    PitchDetection, PitchDetector,
+};
+
+/// Number of thresholds sampled from the beta prior in every frame
+const NUM_THRESHOLDS: usize = 100;
+
+/// Probability mass given to the global minimum of the CMND for thresholds that no dip crosses
+const ABSOLUTE_MIN_PROB: f64 = 0.01;
+
+/// Maximum rate at which the pitch can change, in octaves per second
+const MAX_TRANSITION_RATE: f64 = 35.92;
+
+/// A pitch candidate found in a single frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PYinCandidate {
+    /// The frequency of the candidate in hertz.
+    pub freq: f64,
+
+    /// The probability of the candidate being the pitch of the frame.
+    pub prob: f64,
+}
+
+/// A single frame of a pYIN pitch track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PYinFrame {
+    /// Time of the center of the frame, in seconds from the beginning of the signal.
+    pub time: f64,
+
+    /// The decoded pitch of the frame, or `None` if the frame was decoded as unvoiced.
+    pub freq: Option<f64>,
+
+    /// The probability that the frame is voiced, before any tracking.
+    pub voiced_prob: f64,
+}
+
+/// Probability of each of the thresholds `(i + 1) / NUM_THRESHOLDS` under a beta distribution, computed into `probs`
+fn beta_threshold_probs(alpha: f64, beta: f64, probs: &mut Vec<f64>) {
+    probs.clear();
+    probs.extend((0..NUM_THRESHOLDS).map(|i| {
+        let x = (i as f64 + 0.5) / NUM_THRESHOLDS as f64;
+        x.powf(alpha - 1.0) * (1.0 - x).powf(beta - 1.0)
+    }));
+    let total: f64 = probs.iter().sum();
+    probs.iter_mut().for_each(|p| *p /= total);
+}
+
+/// Maps every threshold to the first dip of the CMND below it, and accumulates the prior probability of the
+/// thresholds on the dips. Thresholds that no dip crosses give a small amount of probability to the global minimum.
+/// The dips are collected into `troughs` along with their probabilities, and the candidates in `freq_range` into
+/// `candidates`.
+fn frame_candidates(
+    cmnd: &[f64],
+    threshold_probs: &[f64],
+    min_lag: usize,
+    sample_rate: f64,
+    freq_range: &Range<f64>,
+    troughs: &mut Vec<(usize, f64)>,
+    candidates: &mut Vec<PYinCandidate>,
+) {
+    let min_lag = min_lag.max(1);
+    troughs.clear();
+    troughs.extend(
+        (min_lag..cmnd.len().saturating_sub(1))
+            .filter(|&tau| cmnd[tau] < cmnd[tau - 1] && cmnd[tau] <= cmnd[tau + 1])
+            .map(|tau| (tau, 0.0)),
+    );
+
+    for (i, prob) in threshold_probs.iter().enumerate() {
+        let threshold = (i + 1) as f64 / NUM_THRESHOLDS as f64;
+        match troughs.iter().position(|&(tau, _)| cmnd[tau] < threshold) {
+            Some(idx) => troughs[idx].1 += prob,
+            None => {
+                if let Some(idx) = (0..troughs.len()).reduce(|best, idx| {
+                    if cmnd[troughs[idx].0] < cmnd[troughs[best].0] {
+                        idx
+                    } else {
+                        best
+                    }
+                }) {
+                    troughs[idx].1 += prob * ABSOLUTE_MIN_PROB;
+                }
+            }
+        }
+    }
+
+    candidates.clear();
+    candidates.extend(
+        troughs
+            .iter()
+            .filter(|(_, prob)| *prob > 0.0)
+            .map(|&(tau, prob)| PYinCandidate {
+                freq: sample_rate / parabolic_interpolation(cmnd, tau).x,
+                prob,
+            })
+            .filter(|candidate| freq_range.contains(&candidate.freq)),
+    );
+}
+
+/// Log-spaced pitch bins used as the hidden states of the HMM
+struct PitchBins {
+    min_freq: f64,
+    bins_per_semitone: usize,
+    len: usize,
+}
+
+impl PitchBins {
+    fn new(freq_range: &Range<f64>, bins_per_semitone: usize) -> Self {
+        let len = (12.0 * bins_per_semitone as f64 * (freq_range.end / freq_range.start).log2())
+            .ceil() as usize
+            + 1;
+        Self {
+            min_freq: freq_range.start,
+            bins_per_semitone,
+            len,
+        }
+    }
+
+    fn freq_to_bin(&self, freq: f64) -> Option<usize> {
+        let bin = (12.0 * self.bins_per_semitone as f64 * (freq / self.min_freq).log2()).round();
+        (bin >= 0.0 && (bin as usize) < self.len).then_some(bin as usize)
+    }
+
+    fn bin_to_freq(&self, bin: usize) -> f64 {
+        self.min_freq * 2f64.powf(bin as f64 / (12.0 * self.bins_per_semitone as f64))
+    }
+}
+
+/// Decodes the most likely sequence of states with the Viterbi algorithm. States `0..num_bins` are voiced and
+/// `num_bins..2 * num_bins` are unvoiced. The pitch can only move `half_width` bins between frames, weighted by a
+/// triangular window, and the voicing only changes with probability `switch_prob`.
+fn viterbi(
+    observations: &[Vec<f64>],
+    num_bins: usize,
+    half_width: usize,
+    switch_prob: f64,
+) -> Vec<usize> {
+    let num_states = 2 * num_bins;
+    let weights: Vec<f64> = (0..=half_width)
+        .map(|d| (half_width + 1 - d) as f64)
+        .collect();
+    // Transition weights are normalized per source bin, since bins close to the edges have fewer neighbors
+    let log_transition: Vec<Vec<f64>> = (0..num_bins)
+        .map(|from| {
+            let lo = from.saturating_sub(half_width);
+            let hi = (from + half_width).min(num_bins - 1);
+            let total: f64 = (lo..=hi).map(|to| weights[from.abs_diff(to)]).sum();
+            (lo..=hi)
+                .map(|to| (weights[from.abs_diff(to)] / total).ln())
+                .collect()
+        })
+        .collect();
+    let log_stay = (1.0 - switch_prob).ln();
+    let log_switch = switch_prob.ln();
+
+    let mut log_probs: Vec<f64> = observations[0]
+        .iter()
+        .map(|o| (o / num_states as f64).max(f64::MIN_POSITIVE).ln())
+        .collect();
+    let mut backpointers: Vec<Vec<usize>> = Vec::with_capacity(observations.len());
+    for obs in observations.iter().skip(1) {
+        let mut next = vec![f64::NEG_INFINITY; num_states];
+        let mut pointers = vec![0; num_states];
+        for (from, transitions) in log_transition.iter().enumerate() {
+            let lo = from.saturating_sub(half_width);
+            for (to, log_t) in (lo..).zip(transitions) {
+                for (from_state, to_state, log_v) in [
+                    (from, to, log_stay),
+                    (from, to + num_bins, log_switch),
+                    (from + num_bins, to + num_bins, log_stay),
+                    (from + num_bins, to, log_switch),
+                ] {
+                    let candidate = log_probs[from_state] + log_t + log_v;
+                    if candidate > next[to_state] {
+                        next[to_state] = candidate;
+                        pointers[to_state] = from_state;
+                    }
+                }
+            }
+        }
+        // Observations are floored so that impossible frames don't make every path equally impossible
+        next.iter_mut()
+            .zip(obs)
+            .for_each(|(p, o)| *p += o.max(f64::MIN_POSITIVE).ln());
+        log_probs = next;
+        backpointers.push(pointers);
+    }
+
+    let mut state = (0..num_states)
+        .reduce(|best, s| {
+            if log_probs[s] > log_probs[best] {
+                s
+            } else {
+                best
+            }
+        })
+        .expect("There should be at least one state");
+    let mut path = vec![state; observations.len()];
+    for (t, pointers) in backpointers.iter().enumerate().rev() {
+        state = pointers[state];
+        path[t] = state;
+    }
+    path
+}
+
+/// Probabilistic YIN pitch tracker. Every frame yields multiple pitch candidates, one per threshold drawn from a
+/// beta prior, and a hidden Markov model with voiced and unvoiced states is decoded across all frames of the
+/// signal to produce a smooth pitch track.
+#[derive(Debug, Clone)]
+pub struct PYinDetector {
+    fft_space: Option<FftSpace>,
+
+    /// Number of samples analyzed in each frame
+    frame_len: usize,
+
+    /// Number of samples between the beginning of consecutive frames
+    hop_len: usize,
+
+    /// Parameters of the beta distribution of the YIN thresholds. The default of (2, 18) has a mean of 0.1
+    beta_params: (f64, f64),
+
+    /// Probability of switching from a voiced to an unvoiced state or vice versa between frames
+    switch_prob: f64,
+
+    /// Resolution of the pitch states of the HMM
+    bins_per_semitone: usize,
+
+    /// Samples of the last signal converted to `f64`, and the intermediate results of its last frame
+    samples: Vec<f64>,
+    cmnd: Vec<f64>,
+    threshold_probs: Vec<f64>,
+    troughs: Vec<(usize, f64)>,
+    candidates: Vec<PYinCandidate>,
+}
+
+impl PYinDetector {
+    pub fn new(frame_len: usize, hop_len: usize) -> Self {
+        Self {
+            frame_len,
+            hop_len,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_beta_params(self, alpha: f64, beta: f64) -> Self {
+        Self {
+            beta_params: (alpha, beta),
+            ..self
+        }
+    }
+
+    pub fn with_switch_prob(self, switch_prob: f64) -> Self {
+        Self {
+            switch_prob,
+            ..self
+        }
+    }
+
+    pub fn with_bins_per_semitone(self, bins_per_semitone: usize) -> Self {
+        Self {
+            bins_per_semitone,
+            ..self
+        }
+    }
+
+    /// Returns the pitch candidates of a single frame, along with their probabilities. The sum of the probabilities
+    /// is the probability of the frame being voiced.
+    pub fn detect_candidates_in_range(
+        &mut self,
+        frame: &[f64],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<Vec<PYinCandidate>, PitchError> {
+        self.compute_candidates(frame, sample_rate, freq_range)?;
+        Ok(self.candidates.clone())
+    }
+
+    /// Computes the pitch candidates of a single frame into `self.candidates`.
+    fn compute_candidates(
+        &mut self,
+        frame: &[f64],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<(), PitchError> {
+        if freq_range.start <= 0.0 || freq_range.end <= freq_range.start {
+            return Err(PitchError::IncorrectParameters(format!(
+                "Invalid frequency range: {:?}",
+                freq_range
+            )));
+        }
+        let min_lag = (sample_rate / freq_range.end).floor() as usize;
+        let max_lag = (sample_rate / freq_range.start).ceil() as usize + 1;
+        let fft_len = frame.len() + max_lag;
+        let fft_space = self.fft_space.get_or_insert_with(|| FftSpace::new(fft_len));
+        if fft_space.signal_len() != fft_len {
+            fft_space.resize(fft_len);
+        }
+        difference_function(fft_space, frame, max_lag, &mut self.cmnd);
+        cumulative_mean_normalized_difference(&mut self.cmnd);
+        beta_threshold_probs(
+            self.beta_params.0,
+            self.beta_params.1,
+            &mut self.threshold_probs,
+        );
+        frame_candidates(
+            &self.cmnd,
+            &self.threshold_probs,
+            min_lag,
+            sample_rate,
+            &freq_range,
+            &mut self.troughs,
+            &mut self.candidates,
+        );
+        Ok(())
+    }
+
+    /// Splits the signal in frames and decodes the most likely pitch track across all of them.
+    pub fn detect_pitch_track_in_range(
+        &mut self,
+        signal: &[f64],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<Vec<PYinFrame>, PitchError> {
+        if self.frame_len == 0 || self.hop_len == 0 {
+            return Err(PitchError::IncorrectParameters(
+                "Frame and hop lengths must be greater than 0".to_string(),
+            ));
+        }
+        if signal.len() < self.frame_len {
+            return Err(PitchError::IncorrectParameters(format!(
+                "Signal of length {} is shorter than a frame",
+                signal.len()
+            )));
+        }
+
+        let bins = PitchBins::new(&freq_range, self.bins_per_semitone);
+        let mut frames_candidates = Vec::new();
+        let mut observations = Vec::new();
+        for start in (0..=signal.len() - self.frame_len).step_by(self.hop_len) {
+            let candidates = self.detect_candidates_in_range(
+                &signal[start..start + self.frame_len],
+                sample_rate,
+                freq_range.clone(),
+            )?;
+            let mut obs = vec![0.0; 2 * bins.len];
+            for candidate in candidates.iter() {
+                if let Some(bin) = bins.freq_to_bin(candidate.freq) {
+                    obs[bin] += candidate.prob;
+                }
+            }
+            let voiced_prob: f64 = candidates.iter().map(|c| c.prob).sum::<f64>().min(1.0);
+            obs[bins.len..].fill((1.0 - voiced_prob) / bins.len as f64);
+            frames_candidates.push((start, voiced_prob, candidates));
+            observations.push(obs);
+        }
+
+        let max_semitones_per_frame =
+            (MAX_TRANSITION_RATE * 12.0 * self.hop_len as f64 / sample_rate).round() as usize;
+        let half_width = max_semitones_per_frame * self.bins_per_semitone;
+        let path = viterbi(&observations, bins.len, half_width, self.switch_prob);
+
+        Ok(path
+            .into_iter()
+            .zip(frames_candidates)
+            .map(|(state, (start, voiced_prob, candidates))| {
+                let freq = (state < bins.len).then(|| {
+                    candidates
+                        .iter()
+                        .filter(|c| bins.freq_to_bin(c.freq) == Some(state))
+                        .reduce(|best, c| if c.prob > best.prob { c } else { best })
+                        .map_or_else(|| bins.bin_to_freq(state), |c| c.freq)
+                });
+                PYinFrame {
+                    time: (start as f64 + self.frame_len as f64 / 2.0) / sample_rate,
+                    freq,
+                    voiced_prob,
+                }
+            })
+            .collect())
+    }
+}
+
+impl Default for PYinDetector {
+    fn default() -> Self {
+        Self {
+            fft_space: None,
+            frame_len: 4096,
+            hop_len: 512,
+            beta_params: (2.0, 18.0),
+            switch_prob: 0.01,
+            bins_per_semitone: 5,
+            samples: vec![],
+            cmnd: vec![],
+            threshold_probs: vec![],
+            troughs: vec![],
+            candidates: vec![],
+        }
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for PYinDetector {
+    /// Treats the whole signal as a single frame and returns the most probable candidate.
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        let mut samples = std::mem::take(&mut self.samples);
+        let signal = S::to_f64_signal(signal, &mut samples);
+        let detection = self
+            .compute_candidates(signal, sample_rate, freq_range)
+            .and_then(|_| {
+                self.candidates
+                    .iter()
+                    .copied()
+                    .reduce(|best, c| if c.prob > best.prob { c } else { best })
+                    .map(|c| PitchDetection::new(c.freq, c.prob, signal))
+                    .ok_or_else(|| {
+                        PitchError::NoPitchDetected("Did not find any pitch candidates".to_string())
+                    })
+            });
+        self.samples = samples;
+        detection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        constants::{MAX_FREQ, MIN_FREQ},
+        test_utils::{test_freq, test_signal, test_sine_wave},
+        utils::sine_wave_signal,
+    };
+    use float_cmp::ApproxEq;
+
+    test_freq! {tuner_c5: {
+        detector: PYinDetector::default(),
+        file: "tuner_c5.wav",
+        expected_freq: 47.677 // Same as Autocorrelation2, since the whole signal is analyzed as one frame
+    }}
+    test_freq! {cello_open_a: {
+        detector: PYinDetector::default(),
+        file: "cello_open_a.wav",
+        expected_freq: 219.956
+    }}
+    test_freq! {cello_open_d: {
+        detector: PYinDetector::default(),
+        file: "cello_open_d.wav",
+        expected_freq: 147.057
+    }}
+    test_freq! {cello_open_g: {
+        detector: PYinDetector::default(),
+        file: "cello_open_g.wav",
+        expected_freq: 97.626
+    }}
+    test_freq! {cello_open_c: {
+        detector: PYinDetector::default(),
+        file: "cello_open_c.wav",
+        expected_freq: 64.682
+    }}
+
+    #[test]
+    fn it_detects_sine_wave_pitch() -> anyhow::Result<()> {
+        let mut detector = PYinDetector::default();
+        test_sine_wave(&mut detector, 440.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_tracks_recording() -> anyhow::Result<()> {
+        let mut detector = PYinDetector::default();
+        let signal = test_signal("cello_open_d.wav")?;
+        let track = detector.detect_pitch_track_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        assert_eq!(track.len(), (signal.len() - 4096) / 512 + 1);
+        for frame in track {
+            let freq = frame.freq.expect("Every frame should be voiced");
+            assert!(
+                freq.approx_eq(147., (2., 0)),
+                "Expected freq around 147, actual freq: {}",
+                freq
+            );
+            assert!(frame.voiced_prob > 0.5);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_tracks_voicing() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.;
+        let mut signal = sine_wave_signal(16384, 220., SAMPLE_RATE);
+        signal.extend(std::iter::repeat_n(0., 16384));
+        signal.extend(sine_wave_signal(16384, 330., SAMPLE_RATE));
+        let mut detector = PYinDetector::new(2048, 1024);
+        let track =
+            detector.detect_pitch_track_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+
+        let at = |time: f64| {
+            track
+                .iter()
+                .find(|frame| frame.time >= time)
+                .expect("Time should be within the signal")
+        };
+        assert!(at(0.1).freq.unwrap().approx_eq(220., (0.5, 0)));
+        assert!(at(0.55).freq.is_none());
+        assert!(at(0.55).voiced_prob < 0.1);
+        assert!(at(0.95).freq.unwrap().approx_eq(330., (0.5, 0)));
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::ops::Range;
+
+use crate::core::{error::PitchError, sample::Sample, utils::parabolic_interpolation, FftPoint};
+
+use super::{HannedFftDetector, PitchDetection, PitchDetector, ToFrequencyDomain};
+
+/// Number of candidate frequencies evaluated per octave
+const CANDIDATES_PER_OCTAVE: f64 = 120.;
+
+/// A candidate fundamental frequency, along with how strongly its harmonics are present in the signal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShsCandidate {
+    /// The frequency of the candidate in hertz.
+    pub freq: f64,
+
+    /// The salience of the candidate relative to the most salient candidate, in the range [0, 1].
+    pub salience: f64,
+}
+
+/// Subharmonic summation detector, as described by Hermes (1988). Every candidate fundamental frequency is scored by
+/// a weighted sum of the spectral magnitudes at its harmonics, where the weight of the `n`th harmonic is
+/// `compression^(n - 1)`. The spectrum is computed with the [HannedFftDetector].
+#[derive(Debug, Clone)]
+pub struct ShsDetector {
+    fft: HannedFftDetector,
+
+    /// Number of harmonics (including the fundamental) that are summed for every candidate
+    harmonics: usize,
+
+    /// Weight factor applied to each successive harmonic. Typical value is around 0.84
+    compression: f64,
+
+    /// Salience function and its peaks for the last signal
+    salience: Vec<f64>,
+    peaks: Vec<FftPoint>,
+}
+
+impl ShsDetector {
+    pub fn new(harmonics: usize, compression: f64) -> Self {
+        Self {
+            harmonics,
+            compression,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_harmonics(self, harmonics: usize) -> Self {
+        Self { harmonics, ..self }
+    }
+
+    pub fn with_compression(self, compression: f64) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    /// Returns the local maxima of the salience function within `freq_range`, sorted from most to least salient.
+    pub fn detect_candidates_in_range<S: Sample>(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<Vec<ShsCandidate>, PitchError> {
+        let min_freq = freq_range.start;
+        let max_salience = self.compute_peaks(signal, sample_rate, freq_range)?;
+        Ok(self
+            .peaks
+            .iter()
+            .map(|peak| ShsCandidate {
+                freq: candidate_freq(min_freq, peak.x),
+                salience: peak.y / max_salience,
+            })
+            .collect())
+    }
+
+    /// Computes the local maxima of the salience function within `freq_range` into `self.peaks`, sorted from most to
+    /// least salient, and returns the salience of the most salient one.
+    fn compute_peaks<S: Sample>(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<f64, PitchError> {
+        if freq_range.start <= 0.0 || freq_range.end <= freq_range.start {
+            return Err(PitchError::IncorrectParameters(format!(
+                "Invalid frequency range: {:?}",
+                freq_range
+            )));
+        }
+        let nyquist_freq = sample_rate / 2.;
+        self.fft.compute_frequency_domain(
+            signal,
+            Some((
+                0.0..(freq_range.end * self.harmonics as f64).min(nyquist_freq),
+                sample_rate,
+            )),
+        );
+        let spectrum = self.fft.frequency_domain();
+        let magnitude_at = |freq: f64| {
+            let bin = self.fft.freq_to_bin(freq, sample_rate);
+            let lower = bin.floor() as usize;
+            match (spectrum.get(lower), spectrum.get(lower + 1)) {
+                (Some(a), Some(b)) => a + (b - a) * (bin - lower as f64),
+                (Some(a), None) => *a,
+                _ => 0.0,
+            }
+        };
+
+        let num_candidates =
+            (CANDIDATES_PER_OCTAVE * (freq_range.end / freq_range.start).log2()).ceil() as usize;
+        let mut salience = std::mem::take(&mut self.salience);
+        salience.clear();
+        salience.extend((0..num_candidates).map(|idx| {
+            let f0 = candidate_freq(freq_range.start, idx as f64);
+            (1..=self.harmonics)
+                .map(|n| n as f64 * f0)
+                .take_while(|freq| *freq < nyquist_freq)
+                .zip(std::iter::successors(Some(1.0), |w| {
+                    Some(w * self.compression)
+                }))
+                .map(|(freq, weight)| weight * magnitude_at(freq))
+                .sum::<f64>()
+        }));
+
+        let peaks = &mut self.peaks;
+        peaks.clear();
+        peaks.extend(
+            (0..salience.len())
+                .filter(|&idx| {
+                    let left = idx.checked_sub(1).map_or(f64::MIN, |i| salience[i]);
+                    let right = salience.get(idx + 1).cloned().unwrap_or(f64::MIN);
+                    salience[idx] > left && salience[idx] >= right
+                })
+                .map(|idx| parabolic_interpolation(&salience, idx)),
+        );
+        self.salience = salience;
+        peaks.sort_unstable_by(|a, b| b.y.partial_cmp(&a.y).unwrap());
+        match peaks.first() {
+            Some(FftPoint { y, .. }) if *y > 0.0 => Ok(*y),
+            _ => Err(PitchError::NoPitchDetected(
+                "Signal has no energy in the frequency range".to_string(),
+            )),
+        }
+    }
+}
+
+/// Frequency of the candidate at `idx`, which can be fractional, counting from `min_freq`.
+fn candidate_freq(min_freq: f64, idx: f64) -> f64 {
+    min_freq * 2f64.powf(idx / CANDIDATES_PER_OCTAVE)
+}
+
+impl Default for ShsDetector {
+    fn default() -> Self {
+        Self {
+            fft: HannedFftDetector::default(),
+            harmonics: 15,
+            compression: 0.84,
+            salience: vec![],
+            peaks: vec![],
+        }
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for ShsDetector {
+    /// The confidence is how much more salient the best candidate is than the runner-up.
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        let min_freq = freq_range.start;
+        let max_salience = self.compute_peaks(signal, sample_rate, freq_range)?;
+        let runner_up_salience = self.peaks.get(1).map_or(0., |peak| peak.y / max_salience);
+        self.peaks
+            .first()
+            .map(|peak| {
+                PitchDetection::new(
+                    candidate_freq(min_freq, peak.x),
+                    1. - runner_up_salience,
+                    signal,
+                )
+            })
+            .ok_or_else(|| {
+                PitchError::NoPitchDetected("Did not find any pitch candidates".to_string())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        constants::{MAX_FREQ, MIN_FREQ},
+        test_utils::{test_freq, test_signal, test_sine_wave},
+    };
+    use float_cmp::ApproxEq;
+
+    test_freq! {tuner_c5: {
+        detector: ShsDetector::default(),
+        file: "tuner_c5.wav",
+        expected_freq: 47.708 // The low frequency component of the tuner recording has the most salient harmonics
+    }}
+    test_freq! {cello_open_a: {
+        detector: ShsDetector::default(),
+        file: "cello_open_a.wav",
+        expected_freq: 219.916
+    }}
+    test_freq! {cello_open_d: {
+        detector: ShsDetector::default(),
+        file: "cello_open_d.wav",
+        expected_freq: 146.964
+    }}
+    test_freq! {cello_open_g: {
+        detector: ShsDetector::default(),
+        file: "cello_open_g.wav",
+        expected_freq: 97.426
+    }}
+    test_freq! {cello_open_c: {
+        detector: ShsDetector::default(),
+        file: "cello_open_c.wav",
+        expected_freq: 64.676
+    }}
+
+    #[test]
+    fn test_from_sine_wave() -> anyhow::Result<()> {
+        let mut detector = ShsDetector::default();
+        test_sine_wave(&mut detector, 440.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_ranks_candidates() -> anyhow::Result<()> {
+        let mut detector = ShsDetector::default();
+        let signal = test_signal("cello_open_c.wav")?;
+        let candidates =
+            detector.detect_candidates_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        // Probably C2, maybe C3
+        assert!(candidates[0].freq.approx_eq(64.676, (0.02, 2)));
+        assert_eq!(candidates[0].salience, 1.);
+        assert!(candidates[1].freq.approx_eq(129.332, (0.02, 2)));
+        assert!(candidates[1].salience < 1.);
+        assert!(candidates
+            .windows(2)
+            .all(|pair| pair[0].salience >= pair[1].salience));
+        Ok(())
+    }
+}
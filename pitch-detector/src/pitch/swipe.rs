@@ -0,0 +1,412 @@
+// Rust implementation of the SWIPE' pitch estimation algorithm (Camacho and Harris, 2008)
+use std::ops::Range;
+
+use crate::core::{
+    error::PitchError, fft_space::FftSpace, sample::Sample, utils::parabolic_interpolation,
+};
+
+use super::{PitchDetection, PitchDetector};
+
+/// Distance between pitch candidates, in octaves
+const CANDIDATE_STEP: f64 = 1. / 96.;
+
+/// Distance between the frequencies at which the spectrum is sampled, in ERBs
+const ERB_STEP: f64 = 0.1;
+
+/// Each window size fits `4 * PERIODS_FACTOR` periods of the pitch it is optimal for
+const PERIODS_FACTOR: f64 = 2.;
+
+fn hz_to_erbs(hz: f64) -> f64 {
+    21.4 * (1. + hz / 229.).log10()
+}
+
+fn erbs_to_hz(erbs: f64) -> f64 {
+    (10f64.powf(erbs / 21.4) - 1.) * 229.
+}
+
+/// Sieve of Eratosthenes into `is_prime`, where `1` is also considered prime since SWIPE' always includes the
+/// fundamental
+fn prime_table(max: usize, is_prime: &mut Vec<bool>) {
+    is_prime.clear();
+    is_prime.resize(max + 1, true);
+    is_prime[0] = false;
+    let mut i = 2;
+    while i * i <= max {
+        if is_prime[i] {
+            (i * i..=max).step_by(i).for_each(|j| is_prime[j] = false);
+        }
+        i += 1;
+    }
+}
+
+/// Magnitude spectrum of a hann-windowed frame of `window_len` samples centered in the signal, computed into
+/// `spectrum`. Returns the frequency spacing of its bins.
+fn frame_spectrum(
+    fft_space: &mut FftSpace,
+    signal: &[f64],
+    window_len: usize,
+    sample_rate: f64,
+    spectrum: &mut Vec<f64>,
+) -> f64 {
+    let start = (signal.len() - window_len) / 2;
+    let frame = &signal[start..start + window_len];
+    fft_space.resize(window_len);
+    fft_space.init_with_signal(
+        frame
+            .iter()
+            .zip(apodize::hanning_iter(window_len))
+            .map(|(s, h)| s * h),
+    );
+    fft_space.forward_fft();
+    spectrum.clear();
+    spectrum.extend(
+        fft_space
+            .freq_domain_iter(true)
+            .take(fft_space.padded_len() / 2 + 1)
+            .map(|(amplitude, _)| amplitude),
+    );
+    sample_rate / fft_space.padded_len() as f64
+}
+
+/// Computes the strength of the candidate `pc` from the loudness `loudness` sampled at `erb_freqs`, whose norm is
+/// `loudness_norm`. The kernel of the candidate, which is computed into `kernel`, has positive cosine lobes at its
+/// first and prime harmonics, and negative lobes between them.
+fn pitch_strength(
+    erb_freqs: &[f64],
+    loudness: &[f64],
+    loudness_norm: f64,
+    pc: f64,
+    is_prime: &[bool],
+    kernel: &mut Vec<f64>,
+) -> f64 {
+    if loudness_norm == 0. {
+        return 0.;
+    }
+    let max_freq = erb_freqs[erb_freqs.len() - 1];
+    let num_harmonics = (max_freq / pc - 0.75).trunc() as usize;
+    if num_harmonics == 0 {
+        return 0.;
+    }
+    kernel.clear();
+    kernel.extend(erb_freqs.iter().map(|&f| {
+        let q = f / pc;
+        let mut k = 0.;
+        for i in [q.floor() as usize, q.ceil() as usize] {
+            if i == 0 || i > num_harmonics || !is_prime[i] {
+                continue;
+            }
+            let a = (q - i as f64).abs();
+            if a < 0.25 {
+                k = (2. * std::f64::consts::PI * q).cos();
+            } else if a < 0.75 {
+                k += (2. * std::f64::consts::PI * q).cos() / 2.;
+            }
+        }
+        k * (1. / f).sqrt()
+    }));
+    let kernel_norm = kernel
+        .iter()
+        .filter(|k| **k > 0.)
+        .map(|k| k * k)
+        .sum::<f64>()
+        .sqrt();
+    if kernel_norm == 0. {
+        return 0.;
+    }
+    kernel.iter().zip(loudness).map(|(k, l)| k * l).sum::<f64>() / (kernel_norm * loudness_norm)
+}
+
+/// Intermediate results of [swipe_pitch]
+#[derive(Debug, Clone, Default)]
+struct SwipeBuffers {
+    erb_freqs: Vec<f64>,
+    is_prime: Vec<bool>,
+    strengths: Vec<f64>,
+    used_by: Vec<(usize, f64)>,
+    spectrum: Vec<f64>,
+    loudness: Vec<f64>,
+    kernel: Vec<f64>,
+}
+
+/// Main SWIPE' pitch estimation function. Returns the estimated pitch along with its strength. `fft_space` is resized
+/// for every window size, and keeps the FFT plans of all of them. The frequency range is clamped to the Nyquist
+/// frequency.
+fn swipe_pitch(
+    fft_space: &mut FftSpace,
+    buffers: &mut SwipeBuffers,
+    signal: &[f64],
+    sample_rate: f64,
+    freq_range: Range<f64>,
+) -> Result<(f64, f64), PitchError> {
+    if freq_range.start <= 0.0 || freq_range.end <= freq_range.start {
+        return Err(PitchError::IncorrectParameters(format!(
+            "Invalid frequency range: {:?}",
+            freq_range
+        )));
+    }
+    let nyquist_freq = sample_rate / 2.;
+    if freq_range.start >= nyquist_freq {
+        return Err(PitchError::IncorrectParameters(format!(
+            "Frequency range {:?} starts above the Nyquist frequency",
+            freq_range
+        )));
+    }
+    let freq_range = freq_range.start..freq_range.end.min(nyquist_freq);
+    let SwipeBuffers {
+        erb_freqs,
+        is_prime,
+        strengths,
+        used_by,
+        spectrum,
+        loudness,
+        kernel,
+    } = buffers;
+
+    // Pitch candidates, spaced logarithmically
+    let log2_min = freq_range.start.log2();
+    let num_candidates = ((freq_range.end.log2() - log2_min) / CANDIDATE_STEP).floor() as usize + 1;
+    let log2_candidate = |j: usize| log2_min + j as f64 * CANDIDATE_STEP;
+    let candidate = |j: usize| 2f64.powf(log2_candidate(j));
+
+    // Window sizes, from largest to smallest
+    let optimal_log2_window =
+        |freq: f64| (4. * PERIODS_FACTOR * sample_rate / freq).log2().round() as i32;
+    let largest_log2_window = optimal_log2_window(freq_range.start);
+    let smallest_log2_window = optimal_log2_window(freq_range.end);
+    let max_log2_window = (signal.len() as f64).log2().floor() as i32;
+    if smallest_log2_window > max_log2_window {
+        return Err(PitchError::IncorrectParameters(format!(
+            "Signal of length {} is too short for the frequency range",
+            signal.len()
+        )));
+    }
+    let num_windows = (largest_log2_window - smallest_log2_window) as usize + 1;
+    let log2_window_len = |window_idx: usize| largest_log2_window - window_idx as i32;
+    // Position of every candidate between the optimal pitches of the window sizes, where 0 corresponds to the
+    // largest window size.
+    let largest_window_log2_pitch =
+        (4. * PERIODS_FACTOR * sample_rate / (1usize << largest_log2_window) as f64).log2();
+    let window_position = |j: usize| log2_candidate(j) - largest_window_log2_pitch;
+
+    // Frequencies at which the spectrum is sampled, uniformly spaced on the ERB scale
+    let min_erbs = hz_to_erbs(candidate(0) / 4.);
+    let max_erbs = hz_to_erbs(nyquist_freq);
+    erb_freqs.clear();
+    erb_freqs.extend(
+        (0..((max_erbs - min_erbs) / ERB_STEP).floor() as usize)
+            .map(|i| erbs_to_hz(min_erbs + i as f64 * ERB_STEP)),
+    );
+    let max_erb_freq = *erb_freqs.last().ok_or_else(|| {
+        PitchError::IncorrectParameters(format!(
+            "Frequency range {:?} leaves no room to sample the spectrum below the Nyquist frequency",
+            freq_range
+        ))
+    })?;
+    prime_table((max_erb_freq / candidate(0)).ceil() as usize + 1, is_prime);
+
+    // Windows that don't fit in the signal are replaced by the largest one that does
+    let first_window = (0..num_windows)
+        .position(|window_idx| log2_window_len(window_idx) <= max_log2_window)
+        .expect("At least one window should fit in the signal") as f64;
+    let last_window = (num_windows - 1) as f64;
+
+    strengths.clear();
+    strengths.resize(num_candidates, 0.);
+    for window_idx in 0..num_windows {
+        // Candidates use the two window sizes whose optimal pitches surround them, weighted by proximity
+        used_by.clear();
+        used_by.extend((0..num_candidates).filter_map(|j| {
+            let position = window_position(j).clamp(first_window, last_window);
+            let distance = (position - window_idx as f64).abs();
+            (distance < 1.).then_some((j, 1. - distance))
+        }));
+        if used_by.is_empty() {
+            continue;
+        }
+
+        let window_len = 1 << log2_window_len(window_idx);
+        let bin_width = frame_spectrum(fft_space, signal, window_len, sample_rate, spectrum);
+        // Loudness is approximated by the square root of the magnitude
+        loudness.clear();
+        loudness.extend(erb_freqs.iter().map(|f| {
+            let bin = f / bin_width;
+            let lower = bin.floor() as usize;
+            let magnitude = match (spectrum.get(lower), spectrum.get(lower + 1)) {
+                (Some(a), Some(b)) => a + (b - a) * (bin - lower as f64),
+                _ => 0.,
+            };
+            magnitude.max(0.).sqrt()
+        }));
+        let loudness_norm = loudness.iter().map(|l| l * l).sum::<f64>().sqrt();
+        for (j, weight) in used_by.iter() {
+            strengths[*j] += weight
+                * pitch_strength(
+                    erb_freqs,
+                    loudness,
+                    loudness_norm,
+                    candidate(*j),
+                    is_prime,
+                    kernel,
+                );
+        }
+    }
+
+    let best = (0..strengths.len())
+        .reduce(|best, j| {
+            if strengths[j] > strengths[best] {
+                j
+            } else {
+                best
+            }
+        })
+        .ok_or_else(|| {
+            PitchError::IncorrectParameters("Frequency range has no pitch candidates".to_string())
+        })?;
+    let peak = parabolic_interpolation(strengths, best);
+    Ok((2f64.powf(log2_min + peak.x * CANDIDATE_STEP), peak.y))
+}
+
+#[derive(Debug, Clone)]
+pub struct SwipeDetector {
+    fft_space: Option<FftSpace>,
+
+    /// Minimum pitch strength for the signal to be considered pitched. Strength ranges from -1 to 1, and a typical
+    /// threshold is around 0.3
+    strength_threshold: f64,
+
+    /// Samples of the last signal converted to `f64` and the intermediate results of SWIPE'
+    samples: Vec<f64>,
+    buffers: SwipeBuffers,
+}
+
+impl SwipeDetector {
+    pub fn new(strength_threshold: f64) -> Self {
+        Self {
+            fft_space: None,
+            strength_threshold,
+            samples: vec![],
+            buffers: SwipeBuffers::default(),
+        }
+    }
+}
+
+impl Default for SwipeDetector {
+    fn default() -> Self {
+        Self {
+            fft_space: None,
+            strength_threshold: 0.3,
+            samples: vec![],
+            buffers: SwipeBuffers::default(),
+        }
+    }
+}
+
+impl<S: Sample> PitchDetector<S> for SwipeDetector {
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        let signal = S::to_f64_signal(signal, &mut self.samples);
+        let fft_space = self
+            .fft_space
+            .get_or_insert_with(|| FftSpace::new(signal.len()));
+        let (pitch, strength) = swipe_pitch(
+            fft_space,
+            &mut self.buffers,
+            signal,
+            sample_rate,
+            freq_range,
+        )?;
+        if strength < self.strength_threshold {
+            return Err(PitchError::NoPitchDetected(format!(
+                "Pitch strength {:.3} did not exceed threshold",
+                strength
+            )));
+        }
+        Ok(PitchDetection::new(pitch, strength, signal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        constants::{MAX_FREQ, MIN_FREQ},
+        test_utils::test_freq,
+        utils::sine_wave_signal,
+    };
+
+    test_freq! {tuner_c5: {
+        detector: SwipeDetector::default(),
+        file: "tuner_c5.wav",
+        expected_freq: 47.697 // The low frequency component of the tuner recording is the strongest pitch
+    }}
+    test_freq! {cello_open_a: {
+        detector: SwipeDetector::default(),
+        file: "cello_open_a.wav",
+        expected_freq: 219.490
+    }}
+    test_freq! {cello_open_d: {
+        detector: SwipeDetector::default(),
+        file: "cello_open_d.wav",
+        expected_freq: 147.081
+    }}
+    test_freq! {cello_open_g: {
+        detector: SwipeDetector::default(),
+        file: "cello_open_g.wav",
+        expected_freq: 97.583
+    }}
+    test_freq! {cello_open_c: {
+        detector: SwipeDetector::default(),
+        file: "cello_open_c.wav",
+        expected_freq: 64.637
+    }}
+
+    #[test]
+    fn test_from_sine_wave() -> anyhow::Result<()> {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let mut detector = SwipeDetector::default();
+        let signal = sine_wave_signal(16384, 440., SAMPLE_RATE);
+        let freq = detector.detect_pitch_in_range(&signal, SAMPLE_RATE, MIN_FREQ..MAX_FREQ)?;
+        // The spectrum is sampled on the ERB scale, so the estimate is accurate to a few cents
+        let cents = 1200. * (freq / 440.).log2();
+        assert!(
+            cents.abs() < 10.,
+            "Expected freq: 440, Actual freq: {}",
+            freq
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_ranges_above_the_nyquist_frequency() {
+        let mut detector = SwipeDetector::default();
+        let signal = sine_wave_signal(16384, 440., 44100.);
+        for freq_range in [100000.0..200000., 22050.0..30000.] {
+            assert!(matches!(
+                detector.detect_pitch_in_range(&signal, 44100., freq_range),
+                Err(PitchError::IncorrectParameters(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn it_clamps_ranges_to_the_nyquist_frequency() -> anyhow::Result<()> {
+        let mut detector = SwipeDetector::default();
+        let signal = sine_wave_signal(16384, 440., 44100.);
+        let freq = detector.detect_pitch_in_range(&signal, 44100., 100.0..1e6)?;
+        assert!((freq - 440.).abs() < 5., "Actual freq: {}", freq);
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_silence() {
+        let mut detector = SwipeDetector::default();
+        let signal = vec![0.; 16384];
+        assert!(detector
+            .detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)
+            .is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::{collections::VecDeque, ops::Range};
+
+use crate::core::{
+    constants::{MAX_FREQ, MIN_FREQ},
+    error::PitchError,
+    sample::Sample,
+};
+
+use super::{PitchDetection, PitchDetector};
+
+/// A single frame analyzed by a [PitchTracker].
+#[derive(Debug, Clone)]
+pub struct TrackedFrame {
+    /// The time of the center of the frame in seconds, relative to the first sample pushed to the tracker.
+    pub time: f64,
+
+    /// The result of running the detector on the frame.
+    pub result: Result<PitchDetection, PitchError>,
+}
+
+/// Tracks the pitch of a stream of samples that arrive in chunks of any size, such as the buffers of an audio input
+/// device. The tracker keeps the last `window_len` samples in a ring buffer, and runs the detector on them every
+/// `hop_len` samples, once the buffer has filled up for the first time.
+/// ## Examples
+/// ```rust
+/// use pitch_detector::{
+///     core::utils::sine_wave_signal,
+///     pitch::{HannedFftDetector, PitchTracker},
+/// };
+/// const SAMPLE_RATE: f64 = 44100.0;
+///
+/// let mut tracker = PitchTracker::new(HannedFftDetector::default(), SAMPLE_RATE, 4096, 1024);
+/// let signal = sine_wave_signal(8192, 440., SAMPLE_RATE);
+/// let frames: Vec<_> = signal
+///     .chunks(500)
+///     .flat_map(|chunk| tracker.push(chunk))
+///     .collect();
+///
+/// assert_eq!(frames.len(), 5);
+/// assert!(frames.iter().all(|frame| frame.result.is_ok()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PitchTracker<D> {
+    detector: D,
+    sample_rate: f64,
+    freq_range: Range<f64>,
+
+    /// Number of samples analyzed in every frame
+    window_len: usize,
+
+    /// Number of samples between the start of consecutive frames
+    hop_len: usize,
+
+    /// The most recent samples, up to `window_len` of them
+    buffer: VecDeque<f64>,
+
+    /// Number of samples to push before the next frame is analyzed
+    samples_until_frame: usize,
+
+    /// Number of samples pushed since the tracker was created or reset
+    samples_pushed: u64,
+}
+
+impl<D: PitchDetector> PitchTracker<D> {
+    /// Creates a tracker that analyzes `window_len` samples every `hop_len` samples. A `hop_len` of 0 is treated as 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_len` is 0.
+    pub fn new(detector: D, sample_rate: f64, window_len: usize, hop_len: usize) -> Self {
+        assert!(window_len > 0, "Window length must be positive");
+        Self {
+            detector,
+            sample_rate,
+            freq_range: MIN_FREQ..MAX_FREQ,
+            window_len,
+            hop_len: hop_len.max(1),
+            buffer: VecDeque::with_capacity(window_len),
+            samples_until_frame: window_len,
+            samples_pushed: 0,
+        }
+    }
+
+    pub fn with_freq_range(self, freq_range: Range<f64>) -> Self {
+        Self { freq_range, ..self }
+    }
+
+    pub fn detector(&self) -> &D {
+        &self.detector
+    }
+
+    pub fn into_inner(self) -> D {
+        self.detector
+    }
+
+    /// Discards the buffered samples, and restarts the timestamps at 0.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.samples_until_frame = self.window_len;
+        self.samples_pushed = 0;
+    }
+
+    /// Pushes a chunk of samples, and calls `on_frame` for every frame that is analyzed as a result. The samples are
+    /// converted to `f64` as they are buffered.
+    pub fn process<S, F>(&mut self, mut chunk: &[S], mut on_frame: F)
+    where
+        S: Sample,
+        F: FnMut(TrackedFrame),
+    {
+        while !chunk.is_empty() {
+            let take = self.samples_until_frame.min(chunk.len());
+            let (head, rest) = chunk.split_at(take);
+            chunk = rest;
+
+            // Only the last `window_len` samples of the head can end up in the buffer
+            let head = &head[head.len().saturating_sub(self.window_len)..];
+            let overflow = (self.buffer.len() + head.len()).saturating_sub(self.window_len);
+            self.buffer.drain(..overflow);
+            self.buffer.extend(head.iter().map(|s| s.to_f64()));
+            self.samples_pushed += take as u64;
+            self.samples_until_frame -= take;
+
+            if self.samples_until_frame == 0 {
+                self.samples_until_frame = self.hop_len;
+                let start = self.samples_pushed - self.window_len as u64;
+                let time = (start as f64 + self.window_len as f64 / 2.) / self.sample_rate;
+                let result = self.detector.detect_with_confidence_in_range(
+                    self.buffer.make_contiguous(),
+                    self.sample_rate,
+                    self.freq_range.clone(),
+                );
+                on_frame(TrackedFrame { time, result });
+            }
+        }
+    }
+
+    /// Pushes a chunk of samples, and returns the frames that are analyzed as a result.
+    pub fn push<S: Sample>(&mut self, chunk: &[S]) -> Vec<TrackedFrame> {
+        let mut frames = vec![];
+        self.process(chunk, |frame| frames.push(frame));
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{test_utils::test_signal, utils::sine_wave_signal},
+        pitch::HannedFftDetector,
+    };
+    use float_cmp::ApproxEq;
+
+    #[test]
+    fn it_emits_a_frame_every_hop() {
+        const SAMPLE_RATE: f64 = 44100.0;
+        let signal = sine_wave_signal(10000, 440., SAMPLE_RATE);
+        for chunk_len in [1, 333, 1024, 10000] {
+            let mut tracker =
+                PitchTracker::new(HannedFftDetector::default(), SAMPLE_RATE, 4096, 1000);
+            let frames: Vec<TrackedFrame> = signal
+                .chunks(chunk_len)
+                .flat_map(|chunk| tracker.push(chunk))
+                .collect();
+            // Frames end at samples 4096, 5096, ..., 9096
+            assert_eq!(frames.len(), 6);
+            for (i, frame) in frames.iter().enumerate() {
+                let expected_time = (i as f64 * 1000. + 2048.) / SAMPLE_RATE;
+                assert!(frame.time.approx_eq(expected_time, (1e-9, 2)));
+                let freq = frame.result.as_ref().unwrap().freq;
+                assert!((freq - 440.).abs() < 1., "Actual freq: {}", freq);
+            }
+        }
+    }
+
+    #[test]
+    fn it_skips_samples_when_hop_exceeds_window() {
+        let mut tracker = PitchTracker::new(HannedFftDetector::default(), 44100., 1024, 3000);
+        let frames = tracker.push(&sine_wave_signal(10000, 440., 44100.));
+        // Frames end at samples 1024, 4024 and 7024
+        assert_eq!(frames.len(), 3);
+        assert!(frames[2].time.approx_eq(6512. / 44100., (1e-9, 2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Window length must be positive")]
+    fn it_rejects_empty_windows() {
+        PitchTracker::new(HannedFftDetector::default(), 44100., 0, 1024);
+    }
+
+    #[test]
+    fn it_tracks_recordings() -> anyhow::Result<()> {
+        let signal = test_signal("cello_open_a.wav")?;
+        let mut tracker = PitchTracker::new(HannedFftDetector::default(), 44100., 8192, 2048);
+        let frames: Vec<TrackedFrame> = signal
+            .chunks(4096)
+            .flat_map(|chunk| tracker.push(chunk))
+            .collect();
+        assert!(!frames.is_empty());
+        for frame in frames {
+            let freq = frame.result?.freq;
+            assert!((freq - 220.).abs() < 2., "Actual freq: {}", freq);
+        }
+
+        tracker.reset();
+        assert!(tracker.push(&signal[..4096]).is_empty());
+        Ok(())
+    }
+}
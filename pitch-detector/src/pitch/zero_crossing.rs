@@ -0,0 +1,359 @@
+use std::ops::Range;
+
+use crate::core::{
+    constants::MIN_ZERO_CROSSING_RATE,
+    error::PitchError,
+    sample::Sample,
+    utils::{goertzel_magnitude, rms, zero_crossing_rate},
+    window::Window,
+};
+
+use super::{PitchDetection, PitchDetector};
+
+/// Estimates the pitch from the average distance between upward zero crossings, interpolated between samples. This
+/// is very cheap to compute, but only accurate for signals with few partials, since strong harmonics introduce
+/// additional zero crossings.
+#[derive(Debug, Clone, Default)]
+pub struct ZeroCrossingDetector;
+
+impl<S: Sample> PitchDetector<S> for ZeroCrossingDetector {
+    /// The confidence is based on how regular the intervals between the zero crossings are.
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        if signal.is_empty() {
+            return Err(PitchError::IncorrectParameters(
+                "Signal had no elements".to_string(),
+            ));
+        }
+        let mean = signal.iter().map(|s| s.to_f64()).sum::<f64>() / signal.len() as f64;
+        // The crossings are computed on the fly, so that detecting doesn't allocate
+        let crossings = || {
+            signal.windows(2).enumerate().filter_map(|(i, pair)| {
+                let (a, b) = (pair[0].to_f64() - mean, pair[1].to_f64() - mean);
+                (a < 0. && b >= 0.).then(|| i as f64 + a / (a - b))
+            })
+        };
+        let (first, last, num_crossings) =
+            crossings().fold((None, 0., 0), |(first, _, num_crossings), crossing| {
+                (first.or(Some(crossing)), crossing, num_crossings + 1)
+            });
+        let first = match first {
+            Some(first) if num_crossings >= 2 => first,
+            _ => {
+                return Err(PitchError::NoPitchDetected(
+                    "Signal does not cross zero often enough".to_string(),
+                ))
+            }
+        };
+        let count = num_crossings - 1;
+        let mean_period = (last - first) / count as f64;
+        let freq = sample_rate / mean_period;
+
+        // Periodic signals cross zero at regular intervals
+        let period_deviation = (crossings()
+            .zip(crossings().skip(1))
+            .map(|(a, b)| (b - a - mean_period).powi(2))
+            .sum::<f64>()
+            / count as f64)
+            .sqrt();
+        if freq_range.contains(&freq) {
+            Ok(PitchDetection::new(
+                freq,
+                1. - period_deviation / mean_period,
+                signal,
+            ))
+        } else {
+            Err(PitchError::NoPitchDetected(
+                "Detected pitch is outside of the frequency range".to_string(),
+            ))
+        }
+    }
+}
+
+/// Wraps a detector, and rejects signals that look like noise with [PitchError::NoPitchDetected] before running the
+/// wrapped detector. A signal is considered noise if its energy is too low, if it crosses zero less often than a
+/// fundamental within the requested frequency range would, or if it crosses zero more often than the configured
+/// maximum. Silent signals are always rejected.
+///
+/// Signals that cross zero less than [MIN_ZERO_CROSSING_RATE](crate::core::constants::MIN_ZERO_CROSSING_RATE) times
+/// per second are also checked for mains hum, and rejected if most of their energy is at multiples of the mains
+/// frequencies. The hum check can only tell hum apart from notes that are a few Hz away from it with buffers long
+/// enough to resolve them, such as 16384 samples at 44.1 kHz.
+#[derive(Debug, Clone)]
+pub struct VoicingGate<D> {
+    detector: D,
+
+    /// Minimum number of zero crossings per second, on top of the two crossings per period of the lowest frequency of
+    /// the requested range
+    min_zero_crossing_rate: f64,
+
+    /// Maximum number of zero crossings per second. Broadband noise crosses zero much more often than pitched signals
+    max_zero_crossing_rate: f64,
+
+    /// Root mean square level at or below which the signal is considered silent, with integer samples normalized to
+    /// the `[-1, 1]` range
+    min_rms: f64,
+
+    /// Frequencies of the mains whose hum is rejected. Defaults to both 50 and 60 Hz
+    mains_freqs: Vec<f64>,
+
+    /// Maximum fraction of the energy of the signal at the multiples of a mains frequency
+    max_hum_ratio: f64,
+}
+
+impl<D> VoicingGate<D> {
+    pub fn new(detector: D) -> Self {
+        Self {
+            detector,
+            min_zero_crossing_rate: 0.,
+            max_zero_crossing_rate: f64::INFINITY,
+            min_rms: 0.,
+            mains_freqs: vec![50., 60.],
+            max_hum_ratio: 0.5,
+        }
+    }
+
+    pub fn with_min_zero_crossing_rate(self, min_zero_crossing_rate: f64) -> Self {
+        Self {
+            min_zero_crossing_rate,
+            ..self
+        }
+    }
+
+    pub fn with_max_zero_crossing_rate(self, max_zero_crossing_rate: f64) -> Self {
+        Self {
+            max_zero_crossing_rate,
+            ..self
+        }
+    }
+
+    pub fn with_min_rms(self, min_rms: f64) -> Self {
+        Self { min_rms, ..self }
+    }
+
+    /// No hum is rejected if `mains_freqs` is empty.
+    pub fn with_mains_freqs(self, mains_freqs: Vec<f64>) -> Self {
+        Self {
+            mains_freqs,
+            ..self
+        }
+    }
+
+    pub fn with_max_hum_ratio(self, max_hum_ratio: f64) -> Self {
+        Self {
+            max_hum_ratio,
+            ..self
+        }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.detector
+    }
+
+    /// Fraction of the energy of the signal at the multiples of the mains frequency that hums the loudest, up to
+    /// [MIN_ZERO_CROSSING_RATE]. A sine at one of those multiples has a ratio of 1.
+    fn hum_ratio<S: Sample>(&self, signal: &[S], sample_rate: f64, level: f64) -> f64 {
+        let len = signal.len();
+        let mean = signal.iter().map(|s| s.to_f64()).sum::<f64>() / len as f64;
+        // A Hann windowed sine of amplitude A has a magnitude of A * len / 4, and an energy of A^2 * len / 2
+        let energy = level * level * len as f64;
+        let normalization = 8. / (len as f64 * energy);
+        self.mains_freqs
+            .iter()
+            .map(|mains_freq| {
+                (1..)
+                    .map(|harmonic| harmonic as f64 * mains_freq)
+                    .take_while(|freq| *freq <= MIN_ZERO_CROSSING_RATE && *freq < sample_rate / 2.)
+                    .map(|freq| {
+                        let windowed = signal
+                            .iter()
+                            .enumerate()
+                            .map(|(i, s)| (s.to_f64() - mean) * Window::Hann.value(i, len));
+                        goertzel_magnitude(windowed, freq, sample_rate).powi(2) * normalization
+                    })
+                    .sum::<f64>()
+            })
+            .fold(0., f64::max)
+    }
+
+    /// Returns an error if the signal looks like noise, or if it is too slow to have a fundamental within
+    /// `freq_range`.
+    pub fn check_voicing<S: Sample>(
+        &self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: &Range<f64>,
+    ) -> Result<(), PitchError> {
+        let level = rms(signal);
+        if level <= self.min_rms {
+            return Err(PitchError::NoPitchDetected(format!(
+                "Signal level {:.3} is too low",
+                level
+            )));
+        }
+        // A periodic signal crosses its mean at least twice per period, in opposite directions
+        let min_rate = self.min_zero_crossing_rate.max(2. * freq_range.start);
+        let rate = zero_crossing_rate(signal, sample_rate);
+        if rate < min_rate || rate > self.max_zero_crossing_rate {
+            return Err(PitchError::NoPitchDetected(format!(
+                "Zero crossing rate of {:.1} per second is typical of noise",
+                rate
+            )));
+        }
+        if rate < MIN_ZERO_CROSSING_RATE {
+            let hum_ratio = self.hum_ratio(signal, sample_rate, level);
+            if hum_ratio > self.max_hum_ratio {
+                return Err(PitchError::NoPitchDetected(format!(
+                    "{:.0}% of the signal energy is mains hum",
+                    100. * hum_ratio
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: Sample, D: PitchDetector<S>> PitchDetector<S> for VoicingGate<D> {
+    fn detect_with_confidence_in_range(
+        &mut self,
+        signal: &[S],
+        sample_rate: f64,
+        freq_range: Range<f64>,
+    ) -> Result<PitchDetection, PitchError> {
+        self.check_voicing(signal, sample_rate, &freq_range)?;
+        self.detector
+            .detect_with_confidence_in_range(signal, sample_rate, freq_range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{
+            constants::{MAX_FREQ, MIN_FREQ},
+            test_utils::{test_freq, test_signal, test_sine_wave},
+            utils::sine_wave_signal,
+        },
+        pitch::HannedFftDetector,
+    };
+
+    test_freq! {tuner_c5: {
+        detector: ZeroCrossingDetector,
+        file: "tuner_c5.wav",
+        expected_freq: 464.011 // Zero crossings are only accurate for signals with few partials, should be around 523 Hz
+    }}
+    test_freq! {cello_open_a: {
+        detector: ZeroCrossingDetector,
+        file: "cello_open_a.wav",
+        expected_freq: 323.386 // Should be around 220 Hz
+    }}
+    test_freq! {cello_open_d: {
+        detector: ZeroCrossingDetector,
+        file: "cello_open_d.wav",
+        expected_freq: 329.589 // Should be around 147 Hz
+    }}
+    test_freq! {cello_open_g: {
+        detector: ZeroCrossingDetector,
+        file: "cello_open_g.wav",
+        expected_freq: 205.032 // Should be around 98 Hz
+    }}
+    test_freq! {cello_open_c: {
+        detector: ZeroCrossingDetector,
+        file: "cello_open_c.wav",
+        expected_freq: 192.535 // Should be around 65 Hz
+    }}
+
+    #[test]
+    fn test_from_sine_wave() -> anyhow::Result<()> {
+        let mut detector = ZeroCrossingDetector;
+        test_sine_wave(&mut detector, 440.)?;
+        Ok(())
+    }
+
+    #[test]
+    fn voicing_gate_rejects_noise() -> anyhow::Result<()> {
+        let mut detector = VoicingGate::new(HannedFftDetector::default());
+        let signal = test_signal("noise.wav")?;
+        assert!(matches!(
+            detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ),
+            Err(PitchError::NoPitchDetected(_))
+        ));
+
+        let signal = vec![0.; 16384];
+        assert!(matches!(
+            detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ),
+            Err(PitchError::NoPitchDetected(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn voicing_gate_accepts_low_sines() -> anyhow::Result<()> {
+        let mut detector = VoicingGate::new(HannedFftDetector::default());
+        for freq in [41.2, 65.41, 82.41, 98., 110., 146.83, 196., 440.] {
+            let signal = sine_wave_signal(16384, freq, 44100.);
+            let actual_freq =
+                detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+            assert!(
+                (actual_freq - freq).abs() < 1.,
+                "Expected freq: {}, Actual freq: {}",
+                freq,
+                actual_freq
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn voicing_gate_rejects_signals_too_slow_for_the_range() {
+        let detector = VoicingGate::new(HannedFftDetector::default());
+        let signal = sine_wave_signal(16384, 65.41, 44100.);
+        assert!(detector
+            .check_voicing(&signal, 44100., &(MIN_FREQ..MAX_FREQ))
+            .is_ok());
+        assert!(matches!(
+            detector.check_voicing(&signal, 44100., &(100.0..MAX_FREQ)),
+            Err(PitchError::NoPitchDetected(_))
+        ));
+    }
+
+    #[test]
+    fn voicing_gate_rejects_hum_only_at_mains_frequencies() {
+        let detector = VoicingGate::new(HannedFftDetector::default());
+        for freq in [50., 60., 100., 120.] {
+            let signal = sine_wave_signal(16384, freq, 44100.);
+            assert!(matches!(
+                detector.check_voicing(&signal, 44100., &(MIN_FREQ..MAX_FREQ)),
+                Err(PitchError::NoPitchDetected(_))
+            ));
+        }
+        let detector = detector.with_mains_freqs(vec![]);
+        for freq in [50., 60., 100., 120.] {
+            let signal = sine_wave_signal(16384, freq, 44100.);
+            assert!(detector
+                .check_voicing(&signal, 44100., &(MIN_FREQ..MAX_FREQ))
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn voicing_gate_accepts_recordings() -> anyhow::Result<()> {
+        let mut detector = VoicingGate::new(HannedFftDetector::default());
+        for file in [
+            "tuner_c5.wav",
+            "cello_open_a.wav",
+            "cello_open_d.wav",
+            "cello_open_g.wav",
+            "cello_open_c.wav",
+        ] {
+            let signal = test_signal(file)?;
+            detector.detect_pitch_in_range(&signal, 44100., MIN_FREQ..MAX_FREQ)?;
+        }
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ mod plot;
 use crate::plot::plot_spectrum;
 use pitch_detector::{
     core::{into_frequency_domain::ToFrequencyDomain, utils::mixed_wave_signal},
-    pitch::{HannedFftDetector, PitchDetector, PowerCepstrum},
+    pitch::{ConstantQDetector, HannedFftDetector, PitchDetector, PowerCepstrum},
 };
 
 const TEST_FILE_SAMPLE_RATE: f64 = 44100.;
@@ -68,6 +68,7 @@ fn main() -> anyhow::Result<()> {
 
     plot_detector_for_files(PowerCepstrum::default(), "Power Cepstrum", &test_files)?;
     plot_detector_for_files(HannedFftDetector::default(), "Hanned", &test_files)?;
+    plot_detector_for_files(ConstantQDetector::default(), "Constant Q", &test_files)?;
 
     plot_detector_for_freqs(HannedFftDetector::default(), "Hanned", vec![440.])?;
     plot_detector_for_freqs(HannedFftDetector::default(), "Hannded", vec![440., 523.])?;
@@ -59,7 +59,7 @@ where
         .draw()?;
 
     chart.draw_series(LineSeries::new(
-        x_vals.iter().zip(y_vals).map(|(x, y)| (*x, y)),
+        x_vals.iter().zip(y_vals).map(|(x, y)| (*x, *y)),
         &RED,
     ))?;
 
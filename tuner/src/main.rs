@@ -3,46 +3,47 @@ mod note_renderers;
 use std::sync::Arc;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, Sample, StreamConfig};
-use dasp_sample::ToSample;
+use cpal::{Device, StreamConfig};
 use note_renderers::cmd_line::CmdLineNoteRenderer;
 use note_renderers::simple_command_line::SimpleCommandLineRenderer;
 use note_renderers::NoteRenderer;
-use pitch_detector::note::detect_note_in_range;
-use pitch_detector::pitch::{Cepstrum2, PowerCepstrum};
+use pitch_detector::core::sample::Sample;
+use pitch_detector::note::Tuning;
+use pitch_detector::pitch::{PitchTracker, PowerCepstrum};
 use tokio::select;
 use tokio_util::sync::CancellationToken;
 
+const SAMPLE_RATE: f64 = 44100.0;
+const MAX_FREQ: f64 = 1046.50; // C6
+const MIN_FREQ: f64 = 32.7; // C1
+
+// Frames overlap, so that a note is rendered every hop while still analyzing enough samples for low notes
+const WINDOW_LEN: usize = 8192;
+const HOP_LEN: usize = 2048;
+
 #[tracing::instrument(skip_all)]
-fn write_input_data<T, Renderer>(input: &[T], renderer: Arc<Renderer>)
-where
-    T: Sample + ToSample<f64>,
+fn write_input_data<T, Renderer>(
+    input: &[T],
+    tracker: &mut PitchTracker<PowerCepstrum>,
+    tuning: &Tuning,
+    renderer: Arc<Renderer>,
+) where
+    T: Sample,
     Renderer: NoteRenderer,
 {
-    const SAMPLE_RATE: f64 = 44100.0;
-    const MAX_FREQ: f64 = 1046.50; // C6
-    const MIN_FREQ: f64 = 32.7; // C1
-
-    let mut detector = PowerCepstrum::new_with_defaults().with_sigmas(0.5);
-    // let mut detector = Cepstrum2;
-
-    // TODO: maybe have the detector work in terms of the Sample trait instead of a specific type
-    // to avoid another allocation
-    let signal = input
-        .iter()
-        .map(|s| s.to_sample::<f64>())
-        .collect::<Vec<f64>>();
-
     // TODO: handle unwraps
-    match detect_note_in_range(&signal, &mut detector, SAMPLE_RATE, MIN_FREQ..MAX_FREQ) {
-        Ok(note) => renderer.render_note(note).unwrap(),
-        Err(e) => renderer.render_no_note(e).unwrap(),
-    }
+    tracker.process(input, |frame| {
+        match frame.result.and_then(|detection| tuning.to_note(detection)) {
+            Ok(note) => renderer.render_note(note).unwrap(),
+            Err(e) => renderer.render_no_note(e).unwrap(),
+        }
+    });
 }
 
 async fn listen_audio<Renderer>(
     config: StreamConfig,
     device: Device,
+    tuning: Tuning,
     renderer: Arc<Renderer>,
 ) -> anyhow::Result<()>
 where
@@ -59,9 +60,18 @@ where
     };
 
     let renderer_clone = renderer.clone();
+    let mut tracker = PitchTracker::new(
+        PowerCepstrum::new_with_defaults().with_sigmas(0.5),
+        SAMPLE_RATE,
+        WINDOW_LEN,
+        HOP_LEN,
+    )
+    .with_freq_range(MIN_FREQ..MAX_FREQ);
     let stream = device.build_input_stream(
         &config,
-        move |data, _: &_| write_input_data::<f32, _>(data, renderer_clone.clone()),
+        move |data, _: &_| {
+            write_input_data::<f32, _>(data, &mut tracker, &tuning, renderer_clone.clone())
+        },
         err_fn,
         None,
     )?;
@@ -86,6 +96,13 @@ where
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
+    // The frequency of A4 can be passed as the first argument, such as 442 for orchestras or 415 for baroque music
+    let tuning = match std::env::args().nth(1) {
+        Some(a4_freq) => Tuning::from_a4_freq(a4_freq.parse()?),
+        None => Tuning::default(),
+    };
+    println!("Tuning: A4 = {} Hz", tuning.reference_freq());
+
     let host = cpal::default_host();
 
     // Set up the input device and stream with the default input config.
@@ -111,7 +128,7 @@ async fn main() -> anyhow::Result<()> {
     println!("Input config: {:?}", config);
 
     let cmd_line_renderer = Arc::new(SimpleCommandLineRenderer);
-    listen_audio(config, device, cmd_line_renderer).await?;
+    listen_audio(config, device, tuning, cmd_line_renderer).await?;
 
     Ok(())
 }